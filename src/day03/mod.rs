@@ -1,3 +1,5 @@
+use std::io::Read;
+
 use aoc_runner_derive::{aoc, aoc_generator};
 use anyhow::Result;
 use crate::utils::AocError::*;
@@ -53,6 +55,147 @@ pub fn solve_part2(input: &[Instruction]) -> Result<i32> {
     ).0)
 }
 
+const KEYWORDS: [&[u8]; 3] = [b"mul(", b"do()", b"don't()"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Totals {
+    pub part1: i64,
+    pub part2: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum ScanState {
+    #[default]
+    Idle,
+    Keyword(usize),
+    First(i64, usize),
+    Second(i64, i64, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    state: ScanState,
+    prefix: [u8; 7],
+    enabled: bool,
+    totals: Totals,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self {
+            state: ScanState::Idle,
+            prefix: [0; 7],
+            enabled: true,
+            totals: Totals::default(),
+        }
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.totals
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Totals {
+        for &b in chunk {
+            self.step(b);
+        }
+        self.totals
+    }
+
+    fn step(&mut self, b: u8) {
+        self.state = match self.state {
+            ScanState::Idle => self.keyword(0, b),
+            ScanState::Keyword(len) => match self.keyword(len, b) {
+                // None of the keywords contain their first character again, so only
+                // the current byte can start a new match after a mismatch.
+                ScanState::Idle => self.keyword(0, b),
+                state => state,
+            },
+            ScanState::First(a, digits) => match b {
+                b'0'..=b'9' if digits < 3 => {
+                    ScanState::First(a * 10 + (b - b'0') as i64, digits + 1)
+                }
+                b',' if digits > 0 => ScanState::Second(a, 0, 0),
+                _ => self.keyword(0, b),
+            },
+            ScanState::Second(a, x, digits) => match b {
+                b'0'..=b'9' if digits < 3 => {
+                    ScanState::Second(a, x * 10 + (b - b'0') as i64, digits + 1)
+                }
+                b')' if digits > 0 => {
+                    self.totals.part1 += a * x;
+                    if self.enabled {
+                        self.totals.part2 += a * x;
+                    }
+                    ScanState::Idle
+                }
+                _ => self.keyword(0, b),
+            },
+        };
+    }
+
+    fn keyword(&mut self, len: usize, b: u8) -> ScanState {
+        self.prefix[len] = b;
+        let candidate = &self.prefix[..=len];
+
+        match candidate {
+            b"mul(" => ScanState::First(0, 0),
+            b"do()" => {
+                self.enabled = true;
+                ScanState::Idle
+            }
+            b"don't()" => {
+                self.enabled = false;
+                ScanState::Idle
+            }
+            _ if KEYWORDS.iter().any(|k| k.starts_with(candidate)) => ScanState::Keyword(len + 1),
+            _ => ScanState::Idle,
+        }
+    }
+}
+
+pub struct RunningTotals<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    scanner: Scanner,
+}
+
+impl<R: Read> RunningTotals<R> {
+    pub fn new(reader: R, chunk_size: usize) -> Self {
+        Self {
+            reader,
+            buffer: vec![0; chunk_size.max(1)],
+            scanner: Scanner::new(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for RunningTotals<R> {
+    type Item = Result<Totals>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            return match self.reader.read(&mut self.buffer) {
+                Ok(0) => None,
+                Ok(n) => Some(Ok(self.scanner.feed(&self.buffer[..n]))),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Some(Err(e.into())),
+            };
+        }
+    }
+}
+
+pub fn scan<R: Read>(reader: R) -> Result<Totals> {
+    RunningTotals::new(reader, 1 << 16)
+        .last()
+        .unwrap_or(Ok(Totals::default()))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -80,4 +223,34 @@ mod test {
         let data = parse(input2())?;
         Ok(assert_eq!(48, solve_part2(&data)?))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn scan_stream() -> Result<()> {
+        let totals = scan(input2().as_bytes())?;
+        Ok(assert_eq!(
+            Totals {
+                part1: 161,
+                part2: 48
+            },
+            totals
+        ))
+    }
+
+    #[test]
+    fn scan_chunk_boundaries() -> Result<()> {
+        let input = input2().repeat(3);
+        for chunk_size in 1..=input.len() {
+            let totals =
+                RunningTotals::new(input.as_bytes(), chunk_size).collect::<Result<Vec<_>>>()?;
+            assert_eq!(input.len().div_ceil(chunk_size), totals.len());
+            assert_eq!(
+                Some(&Totals {
+                    part1: 3 * 161,
+                    part2: 3 * 48
+                }),
+                totals.last()
+            );
+        }
+        Ok(())
+    }
+}
@@ -8,112 +8,232 @@ pub fn input_generator(input: &str) -> String {
     input.to_string()
 }
 
-fn parse_part1(input: &str) -> Result<Vec<String>> {
-    let map = input
-        .lines()
-        .map(|l| l.chars().collect::<Vec<_>>())
-        .collect::<Vec<_>>();
-    let height = map.len();
-    let width = map[0].len();
-
-    let mut lines = input.lines().map(|l| l.to_owned()).collect::<Vec<_>>();
-    let mut columns = vec![];
-    for j in 0..width {
-        let mut col = vec![];
-        (0..height).for_each(|i| {
-            col.push(map[i][j]);
-        });
-        columns.push(col.into_iter().collect::<String>());
-    }
-
-    let mut diagonal1 = vec![];
-    for i in 0..height {
-        let mut d1 = vec![];
-        let mut d2 = vec![];
-        for x in 0..=i {
-            d1.push(map[i - x][x]);
-            if i != height - 1 {
-                d2.push(map[height - 1 - x][width - 1 - i + x]);
+type Pos = (usize, usize);
+type Dir = (isize, isize);
+
+const DIRECTIONS: [Dir; 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+const WILDCARD: char = '.';
+
+#[derive(Debug, Clone)]
+pub struct Grid {
+    cells: Vec<Vec<char>>,
+}
+
+impl Grid {
+    pub fn new(input: &str) -> Self {
+        let cells = input
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        Self { cells }
+    }
+
+    fn get(&self, p: Pos) -> Option<char> {
+        self.cells.get(p.0)?.get(p.1).copied()
+    }
+
+    fn offset(p: Pos, d: Dir, steps: isize) -> Option<Pos> {
+        Some((
+            p.0.checked_add_signed(d.0 * steps)?,
+            p.1.checked_add_signed(d.1 * steps)?,
+        ))
+    }
+
+    fn positions(&self) -> impl Iterator<Item = Pos> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| (0..row.len()).map(move |j| (i, j)))
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<char, usize>,
+    word: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Trie {
+    nodes: Vec<TrieNode>,
+    words: Vec<String>,
+}
+
+impl Trie {
+    pub fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        let mut trie = Trie {
+            nodes: vec![TrieNode::default()],
+            words: vec![],
+        };
+
+        for word in words {
+            let mut node = 0;
+            for c in word.as_ref().chars() {
+                node = match trie.nodes[node].children.get(&c) {
+                    Some(next) => *next,
+                    None => {
+                        trie.nodes.push(TrieNode::default());
+                        let next = trie.nodes.len() - 1;
+                        trie.nodes[node].children.insert(c, next);
+                        next
+                    }
+                };
+            }
+
+            if node != 0 && trie.nodes[node].word.is_none() {
+                trie.nodes[node].word = Some(trie.words.len());
+                trie.words.push(word.as_ref().to_string());
             }
         }
-        diagonal1.push(d1.into_iter().collect::<String>());
-        if !d2.is_empty() {
-            diagonal1.push(d2.into_iter().collect::<String>());
-        }
+
+        trie
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WordMatch {
+    pub word: String,
+    pub start: Pos,
+    pub direction: Dir,
+}
 
-    let mut diagonal2 = vec![];
-    for i in 0..height {
-        let mut d1 = vec![];
-        let mut d2 = vec![];
-        for x in 0..=i {
-            d1.push(map[height - 1 - i + x][x]);
+pub fn find_words<S: AsRef<str>>(grid: &Grid, words: &[S]) -> Vec<WordMatch> {
+    let trie = Trie::new(words);
 
-            if i != height - 1 {
-                d2.push(map[x][width - 1 - i + x]);
+    grid.positions()
+        .flat_map(|start| DIRECTIONS.iter().map(move |d| (start, *d)))
+        .flat_map(|(start, direction)| {
+            let mut found = vec![];
+            let mut node = 0;
+            let mut steps = 0;
+
+            while let Some(c) = Grid::offset(start, direction, steps).and_then(|p| grid.get(p)) {
+                match trie.nodes[node].children.get(&c) {
+                    Some(next) => node = *next,
+                    None => break,
+                }
+
+                if let Some(word) = trie.nodes[node].word {
+                    found.push(WordMatch {
+                        word: trie.words[word].clone(),
+                        start,
+                        direction,
+                    });
+                }
+                steps += 1;
+            }
+
+            found
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shape {
+    cells: Vec<((isize, isize), char)>,
+}
+
+impl Shape {
+    pub fn new(template: &str) -> Self {
+        let cells = template
+            .lines()
+            .enumerate()
+            .flat_map(|(i, l)| {
+                l.chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c != WILDCARD)
+                    .map(move |(j, c)| ((i as isize, j as isize), c))
+            })
+            .collect::<Vec<_>>();
+
+        Self::normalized(cells)
+    }
+
+    fn normalized(mut cells: Vec<((isize, isize), char)>) -> Self {
+        let min_i = cells.iter().map(|(p, _)| p.0).min().unwrap_or(0);
+        let min_j = cells.iter().map(|(p, _)| p.1).min().unwrap_or(0);
+        cells
+            .iter_mut()
+            .for_each(|(p, _)| *p = (p.0 - min_i, p.1 - min_j));
+        cells.sort();
+
+        Self { cells }
+    }
+
+    fn rotate(&self) -> Self {
+        Self::normalized(self.cells.iter().map(|(p, c)| ((p.1, -p.0), *c)).collect())
+    }
+
+    // Distinct orientations, each paired with the fewest clockwise quarter turns producing it.
+    pub fn rotations(&self) -> Vec<(usize, Shape)> {
+        let mut unique: Vec<(usize, Shape)> = vec![(0, self.clone())];
+        let mut current = self.clone();
+        for turns in 1..4 {
+            current = current.rotate();
+            if unique.iter().all(|(_, r)| *r != current) {
+                unique.push((turns, current.clone()));
             }
         }
-        diagonal2.push(d1.into_iter().collect::<String>());
-        if !d2.is_empty() {
-            diagonal2.push(d2.into_iter().collect::<String>());
-        }
+        unique
     }
 
-    lines.append(&mut columns);
-    lines.append(&mut diagonal1);
-    lines.append(&mut diagonal2);
+    fn matches_at(&self, grid: &Grid, origin: Pos) -> bool {
+        self.cells
+            .iter()
+            .all(|(p, c)| Grid::offset(origin, *p, 1).and_then(|q| grid.get(q)) == Some(*c))
+    }
+}
 
-    Ok(lines)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShapeMatch {
+    pub origin: Pos,
+    // Clockwise quarter turns (0-3); symmetric shapes report the smallest that matches.
+    pub rotation: usize,
 }
 
-#[aoc(day04, part1)]
-pub fn solve_part1(input: &str) -> Result<usize> {
-    let input = parse_part1(input)?;
-
-    Ok(input
-        .iter()
-        .map(|l| {
-            l.chars()
-                .collect::<Vec<_>>()
-                .windows(4)
-                .filter(|w| {
-                    w.iter().copied().collect::<String>() == "XMAS"
-                        || w.iter().copied().collect::<String>() == "SAMX"
+pub fn find_shapes(grid: &Grid, shape: &Shape, rotate: bool) -> Vec<ShapeMatch> {
+    let variants = if rotate {
+        shape.rotations()
+    } else {
+        vec![(0, shape.clone())]
+    };
+
+    grid.positions()
+        .flat_map(|origin| {
+            variants
+                .iter()
+                .filter(move |(_, v)| v.matches_at(grid, origin))
+                .map(move |(rotation, _)| ShapeMatch {
+                    origin,
+                    rotation: *rotation,
                 })
-                .count()
         })
-        .sum())
+        .collect()
 }
 
-#[aoc(day04, part2)]
-pub fn solve_part2(input: &str) -> Result<i32> {
-    let map = input
-        .lines()
-        .enumerate()
-        .flat_map(|(i, l)| l.chars().enumerate().map(move |(j, c)| ((i, j), c)))
-        .collect::<HashMap<_, _>>();
-
-    Ok(map
-        .iter()
-        .map(|(p, c)| {
-            if *c != 'A' {
-                return 0;
-            }
+#[aoc(day04, part1)]
+pub fn solve_part1(input: &str) -> Result<usize> {
+    let grid = Grid::new(input);
+    Ok(find_words(&grid, &["XMAS"]).len())
+}
 
-            let top_left = map.get(&(p.0.wrapping_sub(1), p.1.wrapping_sub(1)));
-            let top_right = map.get(&(p.0.wrapping_sub(1), p.1 + 1));
-            let bottom_left = map.get(&(p.0 + 1, p.1.wrapping_sub(1)));
-            let bottom_right = map.get(&(p.0 + 1, p.1 + 1));
-
-            match (top_left, bottom_right, top_right, bottom_left) {
-                (Some('M'), Some('S'), Some('M'), Some('S')) => 1,
-                (Some('M'), Some('S'), Some('S'), Some('M')) => 1,
-                (Some('S'), Some('M'), Some('M'), Some('S')) => 1,
-                (Some('S'), Some('M'), Some('S'), Some('M')) => 1,
-                _ => 0,
-            }
-        })
-        .sum())
+#[aoc(day04, part2)]
+pub fn solve_part2(input: &str) -> Result<usize> {
+    let grid = Grid::new(input);
+    let shape = Shape::new("M.S\n.A.\nM.S");
+    Ok(find_shapes(&grid, &shape, true).len())
 }
 
 #[cfg(test)]
@@ -142,4 +262,90 @@ MXMXAXMASX"
     fn part2() -> Result<()> {
         Ok(assert_eq!(9, solve_part2(input())?))
     }
+
+    #[test]
+    fn multiple_words() -> Result<()> {
+        let grid = Grid::new("CATS\nXAXX\nXXTX");
+        let mut matches = find_words(&grid, &["CAT", "CATS", "TAC"]);
+        matches.sort();
+
+        Ok(assert_eq!(
+            vec![
+                WordMatch {
+                    word: "CAT".to_string(),
+                    start: (0, 0),
+                    direction: (0, 1)
+                },
+                WordMatch {
+                    word: "CAT".to_string(),
+                    start: (0, 0),
+                    direction: (1, 1)
+                },
+                WordMatch {
+                    word: "CATS".to_string(),
+                    start: (0, 0),
+                    direction: (0, 1)
+                },
+                WordMatch {
+                    word: "TAC".to_string(),
+                    start: (0, 2),
+                    direction: (0, -1)
+                },
+                WordMatch {
+                    word: "TAC".to_string(),
+                    start: (2, 2),
+                    direction: (-1, -1)
+                },
+            ],
+            matches
+        ))
+    }
+
+    #[test]
+    fn shape_rotations() -> Result<()> {
+        let grid = Grid::new("AB.\n.C.\nxBA");
+        let shape = Shape::new("AB\n.C");
+
+        assert_eq!(4, shape.rotations().len());
+        assert_eq!(1, find_shapes(&grid, &shape, false).len());
+        Ok(assert_eq!(
+            vec![
+                ShapeMatch {
+                    origin: (0, 0),
+                    rotation: 0
+                },
+                ShapeMatch {
+                    origin: (1, 1),
+                    rotation: 2
+                }
+            ],
+            find_shapes(&grid, &shape, true)
+        ))
+    }
+
+    #[test]
+    fn symmetric_shape_rotations() -> Result<()> {
+        let grid = Grid::new("A.A\n.A.\n...");
+        let shape = Shape::new("A.\n.A");
+
+        let turns = shape
+            .rotations()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 1], turns);
+        Ok(assert_eq!(
+            vec![
+                ShapeMatch {
+                    origin: (0, 0),
+                    rotation: 0
+                },
+                ShapeMatch {
+                    origin: (0, 1),
+                    rotation: 1
+                },
+            ],
+            find_shapes(&grid, &shape, true)
+        ))
+    }
 }
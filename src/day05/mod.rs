@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::utils::AocError::*;
 use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
@@ -50,41 +52,264 @@ pub fn input_generator(input: &str) -> Result<(PageOrders, Vec<PrintOrder>)> {
     Ok((page_orders, print_orders))
 }
 
-fn check(orders: &PageOrders, pages: PagePair) -> bool {
-    orders
-        .iter()
-        .filter(|o| o.1 == pages.0 && o.0 == pages.1)
-        .count()
-        == 0
+#[derive(Debug, Clone, Default)]
+pub struct RuleGraph {
+    successors: HashMap<u32, HashSet<u32>>,
 }
 
-fn find_first_page_with_wrong_order(pages: &PageOrders, p: &PrintOrder) -> Option<(usize, usize)> {
-    for i in 0..(p.len() - 1) {
-        let lhs = p[i];
-        for j in (i + 1)..p.len() {
-            let rhs = p[j];
+impl RuleGraph {
+    pub fn new(orders: &PageOrders) -> Self {
+        let mut graph = Self::default();
+        for o in orders {
+            graph.successors.entry(o.0).or_default().insert(o.1);
+        }
+        graph
+    }
+
+    pub fn insert(&mut self, rule: PagePair) -> bool {
+        self.successors.entry(rule.0).or_default().insert(rule.1)
+    }
+
+    pub fn remove(&mut self, rule: PagePair) -> bool {
+        self.successors
+            .get_mut(&rule.0)
+            .is_some_and(|s| s.remove(&rule.1))
+    }
+
+    fn successors_of(&self, page: u32) -> impl Iterator<Item = u32> + '_ {
+        self.successors.get(&page).into_iter().flatten().copied()
+    }
+
+    pub fn violations(&self, p: &PrintOrder) -> Vec<PagePair> {
+        let positions = p
+            .iter()
+            .enumerate()
+            .map(|(i, page)| (*page, i))
+            .collect::<HashMap<_, _>>();
+
+        let mut violations = p
+            .iter()
+            .enumerate()
+            .flat_map(|(i, page)| {
+                self.successors_of(*page)
+                    .filter(|s| positions.get(s).is_some_and(|j| *j < i))
+                    .map(|s| (*page, s))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        violations.sort();
+        violations
+    }
+
+    pub fn is_correct(&self, p: &PrintOrder) -> bool {
+        self.violations(p).is_empty()
+    }
+
+    pub fn sort(&self, p: &PrintOrder) -> Result<PrintOrder> {
+        let pages = p.iter().copied().collect::<HashSet<_>>();
+        let mut in_degree = p.iter().map(|page| (*page, 0)).collect::<HashMap<_, _>>();
+        for page in &pages {
+            for s in self.successors_of(*page).filter(|s| pages.contains(s)) {
+                *in_degree.entry(s).or_default() += 1;
+            }
+        }
+
+        let mut ready = p
+            .iter()
+            .rev()
+            .filter(|page| in_degree[page] == 0)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut sorted = vec![];
+
+        while let Some(page) = ready.pop() {
+            sorted.push(page);
+            for s in self.successors_of(page).filter(|s| pages.contains(s)) {
+                let d = in_degree.entry(s).or_default();
+                *d -= 1;
+                if *d == 0 {
+                    ready.push(s);
+                }
+            }
+        }
+
+        if sorted.len() < pages.len() {
+            let remaining = pages
+                .into_iter()
+                .filter(|page| in_degree[page] > 0)
+                .collect::<HashSet<_>>();
+            let cycle = self.find_cycle_in(&remaining).unwrap_or_default();
+            return Err(CyclicRules(cycle)).context("Could not sort print order");
+        }
+
+        Ok(sorted)
+    }
+
+    pub fn find_cycle(&self) -> Option<Vec<u32>> {
+        let nodes = self
+            .successors
+            .iter()
+            .flat_map(|(page, s)| s.iter().copied().chain([*page]))
+            .collect::<HashSet<_>>();
+        self.find_cycle_in(&nodes)
+    }
+
+    fn find_cycle_in(&self, nodes: &HashSet<u32>) -> Option<Vec<u32>> {
+        let mut done = HashSet::new();
+        let mut starts = nodes.iter().copied().collect::<Vec<_>>();
+        starts.sort();
+
+        for start in starts {
+            if done.contains(&start) {
+                continue;
+            }
+
+            let mut path = vec![start];
+            let mut stack = vec![self.next_in(start, nodes)];
 
-            let c = check(pages, (lhs, rhs));
-            if !c {
-                return Some((i, j));
+            while let Some(children) = stack.last_mut() {
+                match children.pop() {
+                    Some(child) if path.contains(&child) => {
+                        let from = path.iter().position(|p| *p == child)?;
+                        return Some(path[from..].to_vec());
+                    }
+                    Some(child) if !done.contains(&child) => {
+                        path.push(child);
+                        stack.push(self.next_in(child, nodes));
+                    }
+                    Some(_) => {}
+                    None => {
+                        stack.pop();
+                        done.insert(path.pop()?);
+                    }
+                }
             }
         }
+
+        None
+    }
+
+    fn next_in(&self, page: u32, nodes: &HashSet<u32>) -> Vec<u32> {
+        let mut next = self
+            .successors_of(page)
+            .filter(|s| nodes.contains(s))
+            .collect::<Vec<_>>();
+        next.sort_by(|a, b| b.cmp(a));
+        next
     }
+}
 
-    None
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidityChange {
+    BecameValid(usize),
+    BecameInvalid(usize),
 }
 
-fn is_correct(pages: &PageOrders, p: &PrintOrder) -> bool {
-    find_first_page_with_wrong_order(pages, p).is_none()
+#[derive(Debug, Clone)]
+pub struct Validator {
+    graph: RuleGraph,
+    positions: Vec<HashMap<u32, usize>>,
+    prints_with_page: HashMap<u32, Vec<usize>>,
+    violations: Vec<usize>,
+}
+
+impl Validator {
+    pub fn new(orders: &PageOrders, prints: &[PrintOrder]) -> Self {
+        let graph = RuleGraph::new(orders);
+        let positions = prints
+            .iter()
+            .map(|p| {
+                p.iter()
+                    .enumerate()
+                    .map(|(i, page)| (*page, i))
+                    .collect::<HashMap<_, _>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut prints_with_page: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (idx, p) in positions.iter().enumerate() {
+            for page in p.keys() {
+                prints_with_page.entry(*page).or_default().push(idx);
+            }
+        }
+
+        let violations = prints
+            .iter()
+            .map(|p| graph.violations(p).len())
+            .collect::<Vec<_>>();
+
+        Self {
+            graph,
+            positions,
+            prints_with_page,
+            violations,
+        }
+    }
+
+    pub fn is_valid(&self, idx: usize) -> bool {
+        self.violations.get(idx) == Some(&0)
+    }
+
+    pub fn valid(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.violations.len()).filter(|idx| self.is_valid(*idx))
+    }
+
+    pub fn add_rule(&mut self, rule: PagePair) -> Vec<ValidityChange> {
+        if !self.graph.insert(rule) {
+            return vec![];
+        }
+
+        self.violated_by(rule)
+            .into_iter()
+            .filter_map(|idx| {
+                self.violations[idx] += 1;
+                (self.violations[idx] == 1).then_some(ValidityChange::BecameInvalid(idx))
+            })
+            .collect()
+    }
+
+    pub fn remove_rule(&mut self, rule: PagePair) -> Vec<ValidityChange> {
+        if !self.graph.remove(rule) {
+            return vec![];
+        }
+
+        self.violated_by(rule)
+            .into_iter()
+            .filter_map(|idx| {
+                self.violations[idx] -= 1;
+                (self.violations[idx] == 0).then_some(ValidityChange::BecameValid(idx))
+            })
+            .collect()
+    }
+
+    fn violated_by(&self, rule: PagePair) -> Vec<usize> {
+        let (Some(lhs), Some(rhs)) = (
+            self.prints_with_page.get(&rule.0),
+            self.prints_with_page.get(&rule.1),
+        ) else {
+            return vec![];
+        };
+
+        let candidates = if lhs.len() < rhs.len() { lhs } else { rhs };
+        candidates
+            .iter()
+            .copied()
+            .filter(|idx| {
+                let p = &self.positions[*idx];
+                matches!((p.get(&rule.0), p.get(&rule.1)), (Some(i), Some(j)) if j < i)
+            })
+            .collect()
+    }
 }
 
 #[aoc(day05, part1)]
 pub fn solve_part1(input: &(PageOrders, Vec<PrintOrder>)) -> Result<u32> {
     let (pages, prints) = input;
+    let graph = RuleGraph::new(pages);
 
     let sum = prints
         .iter()
-        .filter(|p| is_correct(pages, p))
+        .filter(|p| graph.is_correct(p))
         .map(|v| v[v.len() / 2])
         .sum::<u32>();
 
@@ -94,27 +319,16 @@ pub fn solve_part1(input: &(PageOrders, Vec<PrintOrder>)) -> Result<u32> {
 #[aoc(day05, part2)]
 pub fn solve_part2(input: &(PageOrders, Vec<PrintOrder>)) -> Result<u32> {
     let (pages, prints) = input;
+    let graph = RuleGraph::new(pages);
 
-    let to_fix = prints
+    prints
         .iter()
-        .filter(|p| !is_correct(pages, p))
-        .collect::<Vec<_>>();
-
-    let sum = to_fix.into_iter().map(|prints| {
-        let mut p = prints.clone();
-        loop {
-            let swap = find_first_page_with_wrong_order(pages, &p);
-            if let Some((i, j)) = swap {
-                p.swap(i, j);
-            } else {
-                break;
-            }
-        }
-
-        p[p.len() / 2]
-    }).sum();
-
-    Ok(sum)
+        .filter(|p| !graph.is_correct(p))
+        .map(|p| {
+            let p = graph.sort(p)?;
+            Ok(p[p.len() / 2])
+        })
+        .sum()
 }
 
 #[cfg(test)]
@@ -167,4 +381,58 @@ mod test {
         let data = parse()?;
         Ok(assert_eq!(123, solve_part2(&data)?))
     }
+
+    #[test]
+    fn violations() -> Result<()> {
+        let (pages, _) = parse()?;
+        let graph = RuleGraph::new(&pages);
+
+        assert_eq!(vec![(97, 75)], graph.violations(&vec![75, 97, 47, 61, 53]));
+        assert_eq!(vec![(29, 13)], graph.violations(&vec![61, 13, 29]));
+        Ok(assert_eq!(
+            vec![97, 75, 47, 29, 13],
+            graph.sort(&vec![97, 13, 75, 29, 47])?
+        ))
+    }
+
+    #[test]
+    fn cycles() -> Result<()> {
+        let (pages, _) = parse()?;
+        assert_eq!(None, RuleGraph::new(&pages).find_cycle());
+
+        let graph = RuleGraph::new(&vec![(1, 2), (2, 3), (3, 1), (3, 4)]);
+        assert_eq!(Some(vec![1, 2, 3]), graph.find_cycle());
+        assert_eq!(vec![5, 4], graph.sort(&vec![5, 4])?);
+
+        let err = graph.sort(&vec![4, 3, 2, 1]).unwrap_err();
+        Ok(assert!(matches!(
+            err.downcast_ref(),
+            Some(CyclicRules(cycle)) if *cycle == vec![1, 2, 3]
+        )))
+    }
+
+    #[test]
+    fn incremental_rules() -> Result<()> {
+        let (pages, prints) = parse()?;
+        let mut validator = Validator::new(&pages, &prints);
+        assert_eq!(vec![0, 1, 2], validator.valid().collect::<Vec<_>>());
+
+        assert_eq!(Vec::<ValidityChange>::new(), validator.add_rule((53, 29)));
+        assert_eq!(
+            vec![ValidityChange::BecameValid(3)],
+            validator.remove_rule((97, 75))
+        );
+        assert_eq!(
+            vec![
+                ValidityChange::BecameInvalid(0),
+                ValidityChange::BecameInvalid(2)
+            ],
+            validator.add_rule((29, 75))
+        );
+        assert_eq!(
+            vec![ValidityChange::BecameInvalid(1)],
+            validator.add_rule((13, 61))
+        );
+        Ok(assert_eq!(vec![3], validator.valid().collect::<Vec<_>>()))
+    }
 }
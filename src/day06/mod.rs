@@ -3,6 +3,7 @@ use std::collections::{HashMap, HashSet};
 use crate::utils::AocError::*;
 use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
 use rayon::prelude::*;
 
 type Coords = (i32, i32);
@@ -40,7 +41,7 @@ fn generate_dirs() -> Vec<Coords> {
     vec![(-1, 0), (0, 1), (1, 0), (0, -1)]
 }
 
-fn find_path(map: &HashMap<Coords, char>) -> Result<Vec<Coords>> {
+fn find_path(map: &HashMap<Coords, char>) -> Result<Vec<(Coords, Coords)>> {
     let mut dirs = generate_dirs().into_iter().cycle();
     let mut pos = find_start(map)?;
     let mut dir = dirs
@@ -50,8 +51,6 @@ fn find_path(map: &HashMap<Coords, char>) -> Result<Vec<Coords>> {
     let mut path = Vec::new();
 
     while pos_is_on_map(map, &pos) {
-        path.push(pos);
-
         let x = pos.0 + dir.0;
         let y = pos.1 + dir.1;
 
@@ -64,6 +63,8 @@ fn find_path(map: &HashMap<Coords, char>) -> Result<Vec<Coords>> {
             }
         }
 
+        path.push((pos, dir));
+
         pos.0 += dir.0;
         pos.1 += dir.1;
     }
@@ -72,7 +73,10 @@ fn find_path(map: &HashMap<Coords, char>) -> Result<Vec<Coords>> {
 }
 
 fn find_visited(map: &HashMap<Coords, char>) -> Result<HashSet<Coords>> {
-    Ok(find_path(map)?.into_iter().collect::<HashSet<_>>())
+    Ok(find_path(map)?
+        .into_iter()
+        .map(|(pos, _)| pos)
+        .collect::<HashSet<_>>())
 }
 
 #[aoc(day06, part1)]
@@ -80,64 +84,430 @@ pub fn solve_part1(input: &HashMap<Coords, char>) -> Result<usize> {
     Ok(find_visited(input)?.len())
 }
 
-fn has_loop(map: &HashMap<Coords, char>, start: Coords) -> Result<bool> {
-    let mut visited = HashSet::new();
-    let mut pos = start;
-    let mut dirs = generate_dirs().into_iter().cycle();
-    let mut dir = dirs.next().ok_or(GenericError).context("No more dirs")?;
-
-    loop {
-        visited.insert((pos, dir));
-
-        // dude...
-        loop {
-            let x = pos.0 + dir.0;
-            let y = pos.1 + dir.1;
-
-            if let Some(c) = map.get(&(x, y)) {
-                if *c == '#' {
-                    dir = dirs
-                        .next()
-                        .ok_or(GenericError)
-                        .context("Ran our of directions")?;
-                } else {
-                    break;
-                }
-            } else {
-                break;
+#[derive(Debug, Clone)]
+pub struct JumpMap {
+    height: i32,
+    width: i32,
+    jumps: [Vec<Option<Coords>>; 4],
+}
+
+impl JumpMap {
+    pub fn new(map: &HashMap<Coords, char>) -> Self {
+        let height = map.keys().map(|p| p.0 + 1).max().unwrap_or(0);
+        let width = map.keys().map(|p| p.1 + 1).max().unwrap_or(0);
+        let dirs = generate_dirs();
+        let mut jumps: [Vec<Option<Coords>>; 4] =
+            std::array::from_fn(|_| vec![None; (height * width) as usize]);
+
+        for (d, dir) in dirs.iter().enumerate() {
+            let mut cells = (0..height)
+                .flat_map(|i| (0..width).map(move |j| (i, j)))
+                .collect::<Vec<_>>();
+            cells.sort_by_key(|p| -(p.0 * dir.0 + p.1 * dir.1));
+
+            for pos in cells {
+                let next = (pos.0 + dir.0, pos.1 + dir.1);
+                let idx = (pos.0 * width + pos.1) as usize;
+                jumps[d][idx] = match map.get(&next) {
+                    None => None,
+                    Some('#') => Some(pos),
+                    Some(_) => jumps[d][(next.0 * width + next.1) as usize],
+                };
             }
         }
 
-        pos.0 += dir.0;
-        pos.1 += dir.1;
+        Self {
+            height,
+            width,
+            jumps,
+        }
+    }
 
-        if visited.contains(&(pos, dir)) {
-            return Ok(true);
+    fn contains(&self, pos: Coords) -> bool {
+        pos.0 >= 0 && pos.0 < self.height && pos.1 >= 0 && pos.1 < self.width
+    }
+
+    pub fn jump(&self, pos: Coords, d: usize, obstacle: Option<Coords>) -> Option<Coords> {
+        if !self.contains(pos) {
+            return None;
         }
 
-        if pos_is_on_map(map, &pos) {
-            visited.insert((pos, dir));
-        } else {
-            return Ok(false);
+        let dir = generate_dirs()[d];
+        let stop = self.jumps[d][(pos.0 * self.width + pos.1) as usize];
+        let distance = |p: Coords| (p.0 - pos.0) * dir.0 + (p.1 - pos.1) * dir.1;
+
+        let blocked = obstacle.filter(|o| {
+            let on_ray = (o.0 - pos.0) * dir.1 == (o.1 - pos.1) * dir.0 && distance(*o) > 0;
+            on_ray && stop.is_none_or(|s| distance(*o) <= distance(s))
+        });
+
+        match blocked {
+            Some(o) => Some((o.0 - dir.0, o.1 - dir.1)),
+            None => stop,
         }
     }
+
+    fn turns(
+        &self,
+        start: Coords,
+        d: usize,
+        policy: TurnPolicy,
+        obstacle: Option<Coords>,
+    ) -> (Vec<(Coords, usize)>, Option<usize>) {
+        let mut seen = HashMap::new();
+        let mut order = vec![];
+        let mut pos = start;
+        let mut d = d;
+
+        while let Some(stop) = self.jump(pos, d, obstacle) {
+            if let Some(first) = seen.get(&(stop, d)) {
+                return (order, Some(*first));
+            }
+
+            seen.insert((stop, d), order.len());
+            order.push((stop, d));
+            pos = stop;
+            d = policy.turn(d);
+        }
+
+        (order, None)
+    }
+
+    pub fn has_loop(&self, start: Coords, obstacle: Option<Coords>) -> bool {
+        self.turns(start, 0, TurnPolicy::Right, obstacle)
+            .1
+            .is_some()
+    }
+
+    pub fn find_loop(&self, start: Coords, obstacle: Option<Coords>) -> Option<Vec<Coords>> {
+        let (turns, first) = self.turns(start, 0, TurnPolicy::Right, obstacle);
+        let cycle = &turns[first?..];
+        let mut path = vec![];
+
+        for (i, (from, _)) in cycle.iter().enumerate() {
+            let (to, d) = cycle[(i + 1) % cycle.len()];
+            Self::walk(&mut path, *from, d, |pos| pos != to);
+        }
+
+        Some(path)
+    }
+
+    pub fn trajectory(
+        &self,
+        start: Coords,
+        d: usize,
+        policy: TurnPolicy,
+        obstacle: Option<Coords>,
+    ) -> Trajectory {
+        let (turns, first) = self.turns(start, d, policy, obstacle);
+        let mut path = vec![start];
+        let mut cycle_start = None;
+        let mut pos = start;
+
+        for (i, (to, d)) in turns.iter().enumerate() {
+            Self::walk(&mut path, pos, *d, |pos| pos != *to);
+            if first == Some(i) {
+                cycle_start = Some(path.len() - 1);
+            }
+            pos = *to;
+        }
+
+        match first {
+            Some(first) => {
+                let (to, d) = turns[first];
+                Self::walk(&mut path, pos, d, |pos| pos != to);
+                path.pop();
+            }
+            None => {
+                let d = turns.last().map_or(d, |t| policy.turn(t.1));
+                Self::walk(&mut path, pos, d, |pos| self.contains(pos));
+                path.pop();
+            }
+        }
+
+        Trajectory { path, cycle_start }
+    }
+
+    fn walk(path: &mut Vec<Coords>, from: Coords, d: usize, until: impl Fn(Coords) -> bool) {
+        let dir = generate_dirs()[d];
+        let mut pos = from;
+        while until(pos) {
+            pos = (pos.0 + dir.0, pos.1 + dir.1);
+            path.push(pos);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TurnPolicy {
+    Left,
+    Right,
+    Reverse,
+}
+
+impl TurnPolicy {
+    fn turn(&self, d: usize) -> usize {
+        match self {
+            TurnPolicy::Left => (d + 3) % 4,
+            TurnPolicy::Right => (d + 1) % 4,
+            TurnPolicy::Reverse => (d + 2) % 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    pub path: Vec<Coords>,
+    pub cycle_start: Option<usize>,
+}
+
+impl Trajectory {
+    pub fn period(&self) -> Option<usize> {
+        Some(self.path.len() - self.cycle_start?)
+    }
+
+    pub fn position(&self, tick: usize) -> Option<Coords> {
+        match (self.cycle_start, self.period()) {
+            (Some(start), Some(period)) if tick >= start => {
+                Some(self.path[start + (tick - start) % period])
+            }
+            _ => self.path.get(tick).copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guard {
+    pub pos: Coords,
+    pub dir: usize,
+    pub policy: TurnPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardOutcome {
+    Exited { tick: usize },
+    Looping { period: usize },
+    Collided { tick: usize, pos: Coords },
+}
+
+pub fn find_guards(map: &HashMap<Coords, char>, policy: TurnPolicy) -> Vec<Guard> {
+    let mut guards = map
+        .iter()
+        .filter_map(|(pos, c)| {
+            let dir = ['^', '>', 'v', '<'].iter().position(|g| g == c)?;
+            Some(Guard {
+                pos: *pos,
+                dir,
+                policy,
+            })
+        })
+        .collect::<Vec<_>>();
+    guards.sort_by_key(|g| g.pos);
+    guards
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - a / b * y)
+    }
+}
+
+// Smallest x >= lower with x = r1 (mod m1) and x = r2 (mod m2), if the two agree.
+fn crt(r1: usize, m1: usize, r2: usize, m2: usize, lower: usize) -> Option<usize> {
+    let (r1, m1, r2, m2) = (r1 as i128, m1 as i128, r2 as i128, m2 as i128);
+    let (g, p, _) = extended_gcd(m1, m2);
+    if (r2 - r1) % g != 0 {
+        return None;
+    }
+
+    let lcm = m1 / g * m2;
+    let x = (r1 + m1 * ((r2 - r1) / g * p).rem_euclid(m2 / g)).rem_euclid(lcm);
+    let lower = lower as i128;
+    let x = if x < lower {
+        x + (lower - x + lcm - 1) / lcm * lcm
+    } else {
+        x
+    };
+    usize::try_from(x).ok()
+}
+
+// First tick at which two guards share a tile or swap tiles, with their positions.
+// Ticks up to the longer transient are checked directly. Afterwards both guards are
+// either gone or cycling, and every meeting is a pair of cycle offsets solved by CRT.
+fn first_meeting(a: &Trajectory, b: &Trajectory) -> Option<(usize, Coords, Coords)> {
+    let horizon = [a, b]
+        .iter()
+        .map(|t| t.cycle_start.unwrap_or(t.path.len()))
+        .max()
+        .unwrap_or(0);
+
+    for tick in 1..=horizon {
+        let (Some(pa), Some(pb)) = (a.position(tick), b.position(tick)) else {
+            continue;
+        };
+        let swapped = a.position(tick - 1) == Some(pb) && b.position(tick - 1) == Some(pa);
+        if pa == pb || swapped {
+            return Some((tick, pa, pb));
+        }
+    }
+
+    let (sa, sb) = (a.cycle_start?, b.cycle_start?);
+    let (ca, cb) = (&a.path[sa..], &b.path[sb..]);
+    let (pa, pb) = (ca.len(), cb.len());
+
+    let mut tiles: HashMap<Coords, Vec<usize>> = HashMap::new();
+    let mut steps: HashMap<(Coords, Coords), Vec<usize>> = HashMap::new();
+    for (k, pos) in cb.iter().enumerate() {
+        tiles.entry(*pos).or_default().push(k);
+        steps.entry((*pos, cb[(k + 1) % pb])).or_default().push(k);
+    }
+
+    let mut first: Option<(usize, Coords, Coords)> = None;
+    for (k, pos) in ca.iter().enumerate() {
+        let next = ca[(k + 1) % pa];
+        let shared = tiles
+            .get(pos)
+            .into_iter()
+            .flatten()
+            .filter_map(|j| Some((crt(sa + k, pa, sb + j, pb, horizon + 1)?, *pos, *pos)));
+        let swapped = steps
+            .get(&(next, *pos))
+            .into_iter()
+            .flatten()
+            .filter_map(|j| Some((crt(sa + k, pa, sb + j, pb, horizon)? + 1, next, *pos)));
+
+        for meeting in shared.chain(swapped) {
+            if first.is_none_or(|f| meeting.0 < f.0) {
+                first = Some(meeting);
+            }
+        }
+    }
+
+    first
+}
+
+pub fn simulate_guards(map: &HashMap<Coords, char>, guards: &[Guard]) -> Vec<GuardOutcome> {
+    let jumps = JumpMap::new(map);
+    let trajectories = guards
+        .iter()
+        .map(|g| jumps.trajectory(g.pos, g.dir, g.policy, None))
+        .collect::<Vec<_>>();
+
+    // A pair only ever collides at its first meeting, provided neither guard has been
+    // taken out by an earlier collision.
+    let mut meetings = (0..guards.len())
+        .tuple_combinations()
+        .filter_map(|(i, j)| {
+            let (tick, pi, pj) = first_meeting(&trajectories[i], &trajectories[j])?;
+            Some((tick, i, j, pi, pj))
+        })
+        .collect::<Vec<_>>();
+    meetings.sort();
+
+    let mut outcomes = vec![None; guards.len()];
+    for group in meetings.chunk_by(|a, b| a.0 == b.0) {
+        let collided = group
+            .iter()
+            .filter(|m| outcomes[m.1].is_none() && outcomes[m.2].is_none())
+            .copied()
+            .collect::<Vec<_>>();
+        for (tick, i, j, pi, pj) in collided {
+            outcomes[i] = Some(GuardOutcome::Collided { tick, pos: pi });
+            outcomes[j] = Some(GuardOutcome::Collided { tick, pos: pj });
+        }
+    }
+
+    trajectories
+        .iter()
+        .zip(outcomes)
+        .map(|(t, outcome)| {
+            outcome.unwrap_or(match t.period() {
+                Some(period) => GuardOutcome::Looping { period },
+                None => GuardOutcome::Exited { tick: t.path.len() },
+            })
+        })
+        .collect()
 }
 
 #[aoc(day06, part2)]
 pub fn solve_part2(input: &HashMap<Coords, char>) -> Result<i32> {
     let start = find_start(input)?;
+    let jumps = JumpMap::new(input);
 
     let count = find_visited(input)?
         .par_iter()
-        .map(|&(i, j)| -> Result<i32> {
-            let mut map = input.clone();
-            map.entry((i, j)).and_modify(|v| *v = '#');
-            let looping_louie = has_loop(&map, start)?;
-            Ok(if looping_louie { 1 } else { 0 })
+        .filter(|&&p| p != start && jumps.has_loop(start, Some(p)))
+        .count();
+
+    Ok(count as i32)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopReport {
+    pub obstacle: Coords,
+    pub length: usize,
+    pub cycle: Vec<Coords>,
+}
+
+pub fn loop_report(map: &HashMap<Coords, char>) -> Result<Vec<LoopReport>> {
+    let start = find_start(map)?;
+    let jumps = JumpMap::new(map);
+
+    let mut report = find_visited(map)?
+        .into_par_iter()
+        .filter(|p| *p != start)
+        .filter_map(|obstacle| {
+            let cycle = jumps.find_loop(start, Some(obstacle))?;
+            Some(LoopReport {
+                obstacle,
+                length: cycle.len(),
+                cycle,
+            })
         })
-        .sum::<Result<i32>>()?;
+        .collect::<Vec<_>>();
+    report.sort_by_key(|r| r.obstacle);
+
+    Ok(report)
+}
+
+pub fn render_patrol(map: &HashMap<Coords, char>) -> Result<String> {
+    let dirs = generate_dirs();
+    let arrows = ['^', '>', 'v', '<'];
+    let mut annotated = map.clone();
+    let mut seen = HashSet::new();
+    let mut previous = None;
+
+    for (pos, dir) in find_path(map)? {
+        let turned = previous.is_some_and(|p| p != dir);
+        let mark = if turned {
+            '+'
+        } else if !seen.insert(pos) {
+            '*'
+        } else {
+            let d = dirs.iter().position(|d| *d == dir).unwrap_or(0);
+            arrows[d]
+        };
+
+        annotated
+            .entry(pos)
+            .and_modify(|c| *c = if *c == '+' { '+' } else { mark });
+        seen.insert(pos);
+        previous = Some(dir);
+    }
 
-    Ok(count)
+    let height = map.keys().map(|p| p.0 + 1).max().unwrap_or(0);
+    let width = map.keys().map(|p| p.1 + 1).max().unwrap_or(0);
+
+    Ok((0..height)
+        .map(|i| {
+            (0..width)
+                .filter_map(|j| annotated.get(&(i, j)))
+                .collect::<String>()
+        })
+        .join("\n"))
 }
 
 #[cfg(test)]
@@ -168,4 +538,162 @@ mod test {
         let data = input_generator(input())?;
         Ok(assert_eq!(6, solve_part2(&data)?))
     }
+
+    #[test]
+    fn jumps() -> Result<()> {
+        let data = input_generator(input())?;
+        let jumps = JumpMap::new(&data);
+
+        assert_eq!(Some((1, 4)), jumps.jump((6, 4), 0, None));
+        assert_eq!(Some((4, 4)), jumps.jump((6, 4), 0, Some((3, 4))));
+        assert_eq!(Some((1, 4)), jumps.jump((6, 4), 0, Some((7, 4))));
+        assert_eq!(Some((6, 2)), jumps.jump((6, 4), 3, Some((6, 5))));
+        assert_eq!(Some((6, 2)), jumps.jump((6, 4), 3, None));
+        Ok(assert!(jumps.has_loop((6, 4), Some((6, 3)))))
+    }
+
+    #[test]
+    fn report() -> Result<()> {
+        let data = input_generator(input())?;
+        let report = loop_report(&data)?;
+
+        assert_eq!(6, report.len());
+        assert_eq!((6, 3), report[0].obstacle);
+        assert_eq!(18, report[0].length);
+        assert_eq!(Some(&(1, 4)), report[0].cycle.last());
+        Ok(assert_eq!(Some(&(1, 5)), report[0].cycle.first()))
+    }
+
+    #[test]
+    fn render() -> Result<()> {
+        let data = input_generator(input())?;
+        let rendered = render_patrol(&data)?;
+        let lines = rendered.lines().collect::<Vec<_>>();
+
+        assert_eq!("....#.....", lines[0]);
+        assert_eq!("....+>>>+#", lines[1]);
+        Ok(assert_eq!(".#+<*<*<+.", lines[6]))
+    }
+
+    #[test]
+    fn trajectories() -> Result<()> {
+        let data = input_generator(input())?;
+        let jumps = JumpMap::new(&data);
+
+        let right = jumps.trajectory((6, 4), 0, TurnPolicy::Right, None);
+        assert_eq!(None, right.cycle_start);
+        assert_eq!(41, right.path.iter().unique().count());
+
+        let looping = jumps.trajectory((6, 4), 0, TurnPolicy::Right, Some((6, 3)));
+        assert_eq!(Some(18), looping.period());
+        assert_eq!(looping.position(5), looping.position(5 + 18));
+
+        let reverse = jumps.trajectory((6, 4), 0, TurnPolicy::Reverse, Some((7, 4)));
+        assert_eq!(Some(5), reverse.cycle_start);
+        Ok(assert_eq!(Some(10), reverse.period()))
+    }
+
+    #[test]
+    fn multiple_guards() -> Result<()> {
+        let data = input_generator(
+            "#.....
+..>..<
+......
+.....^
+.#....",
+        )?;
+
+        let guards = find_guards(&data, TurnPolicy::Left);
+        assert_eq!(3, guards.len());
+        Ok(assert_eq!(
+            vec![
+                GuardOutcome::Collided {
+                    tick: 2,
+                    pos: (1, 4)
+                },
+                GuardOutcome::Collided {
+                    tick: 2,
+                    pos: (1, 3)
+                },
+                GuardOutcome::Exited { tick: 4 },
+            ],
+            simulate_guards(&data, &guards)
+        ))
+    }
+
+    #[test]
+    fn coprime_guard_periods() -> Result<()> {
+        let lengths = [3, 4, 6, 8, 12, 14, 18, 20, 24, 30];
+        let map = (0..60)
+            .map(|r| {
+                let mut row = vec!['.'; 60];
+                if r % 2 == 0 && r / 2 < lengths.len() {
+                    row[0] = '#';
+                    row[1] = '>';
+                    row[lengths[r / 2] + 1] = '#';
+                }
+                row.into_iter().collect::<String>()
+            })
+            .join("\n");
+        let data = input_generator(&map)?;
+
+        let guards = find_guards(&data, TurnPolicy::Reverse);
+        let periods = simulate_guards(&data, &guards)
+            .into_iter()
+            .map(|o| match o {
+                GuardOutcome::Looping { period } => Some(period),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        assert_eq!(Some(lengths.iter().map(|l| 2 * (l - 1)).collect()), periods);
+
+        let mut grid = vec![vec!['.'; 20]; 20];
+        for (pos, c) in [
+            ((10, 0), '#'),
+            ((10, 1), '>'),
+            ((10, 12), '#'),
+            ((0, 7), '#'),
+            ((2, 7), 'v'),
+            ((18, 7), '#'),
+        ] {
+            grid[pos.0][pos.1] = c;
+        }
+        let data = input_generator(&grid.iter().map(|r| r.iter().collect::<String>()).join("\n"))?;
+        let guards = find_guards(&data, TurnPolicy::Reverse);
+        let jumps = JumpMap::new(&data);
+        let (a, b) = (
+            jumps.trajectory(guards[0].pos, guards[0].dir, TurnPolicy::Reverse, None),
+            jumps.trajectory(guards[1].pos, guards[1].dir, TurnPolicy::Reverse, None),
+        );
+        let expected = (1..1000)
+            .find(|t| {
+                a.position(*t) == b.position(*t)
+                    || (a.position(t - 1) == b.position(*t) && b.position(t - 1) == a.position(*t))
+            })
+            .ok_or(GenericError)?;
+        assert!(expected > a.cycle_start.unwrap_or(0).max(b.cycle_start.unwrap_or(0)));
+        assert_eq!(
+            GuardOutcome::Collided {
+                tick: expected,
+                pos: (10, 7)
+            },
+            simulate_guards(&data, &guards)[0]
+        );
+
+        let data = input_generator("#>...<..#")?;
+        let guards = find_guards(&data, TurnPolicy::Reverse);
+        Ok(assert_eq!(
+            vec![
+                GuardOutcome::Collided {
+                    tick: 2,
+                    pos: (0, 3)
+                },
+                GuardOutcome::Collided {
+                    tick: 2,
+                    pos: (0, 3)
+                },
+            ],
+            simulate_guards(&data, &guards)
+        ))
+    }
 }
@@ -1,20 +1,33 @@
-use anyhow::{Context, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::{BuildHasher, Hash, RandomState},
+    str::FromStr,
+};
+
+use anyhow::{Context, Error, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
+use num_bigint::BigUint;
 use rayon::prelude::*;
 
 use crate::utils::AocError::*;
 
 type Num = u64;
-type Equation = (Num, Vec<Num>);
+type Equation<N = Num> = (N, Vec<N>);
 
-fn parse_line(s: &str) -> Result<(Num, Vec<Num>)> {
+fn parse_line<N>(s: &str) -> Result<Equation<N>>
+where
+    N: FromStr,
+    Error: From<N::Err>,
+{
     let mut split = s.split(":");
 
     let lhs = split
         .next()
         .ok_or(GenericError)
         .context("Could not find lhs")?
-        .parse::<Num>()?;
+        .parse::<N>()?;
     let rhs = split
         .next()
         .ok_or(GenericError)
@@ -22,7 +35,7 @@ fn parse_line(s: &str) -> Result<(Num, Vec<Num>)> {
     let values = rhs
         .split(" ")
         .filter(|v| !v.is_empty())
-        .map(|v| -> Result<Num> { Ok(v.parse::<Num>()?) })
+        .map(|v| -> Result<N> { Ok(v.parse::<N>()?) })
         .collect::<Result<Vec<_>>>()?;
 
     Ok((lhs, values))
@@ -33,118 +46,454 @@ pub fn input_generator(input: &str) -> Result<Vec<Equation>> {
     input.lines().map(parse_line).collect::<Result<Vec<_>>>()
 }
 
-fn plus(n: Num, r: &[Num]) -> Vec<Num> {
-    let l = r.len();
-    if l == 1 {
-        return vec![n + r[0]];
+pub fn big_input_generator(input: &str) -> Result<Vec<Equation<BigUint>>> {
+    input.lines().map(parse_line).collect::<Result<Vec<_>>>()
+}
+
+fn factor(n: Num) -> Option<Num> {
+    10u64.checked_pow(n.checked_ilog10().unwrap_or(0) + 1)
+}
+
+fn cc(a: Num, b: Num) -> Option<Num> {
+    a.checked_mul(factor(b)?)?.checked_add(b)
+}
+
+fn big_factor(n: &BigUint) -> BigUint {
+    BigUint::from(10u32).pow(n.to_string().len() as u32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inverse<N = Num> {
+    Impossible,
+    Exact(N),
+    Unknown,
+}
+
+impl<N> From<Option<N>> for Inverse<N> {
+    fn from(v: Option<N>) -> Self {
+        v.map_or(Inverse::Impossible, Inverse::Exact)
     }
+}
 
-    plus(r[l - 1], &r[..l - 1])
-        .into_iter()
-        .map(|v| v + n)
-        .chain(mul(r[l - 1], &r[..l - 1]).into_iter().map(|v| v + n))
-        .collect::<Vec<_>>()
+pub trait Operator<N = Num>: Sync {
+    fn symbol(&self) -> &str;
+
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N>;
+
+    fn invert(&self, _target: &N, _rhs: &N) -> Inverse<N> {
+        Inverse::Unknown
+    }
 }
 
-fn mul(n: Num, r: &[Num]) -> Vec<Num> {
-    let l = r.len();
-    if l == 1 {
-        return vec![n * r[0]];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Add,
+    Mul,
+    Concat,
+    Sub,
+    Div,
+    Xor,
+}
+
+impl Builtin {
+    fn name(&self) -> &str {
+        match self {
+            Builtin::Add => "+",
+            Builtin::Mul => "*",
+            Builtin::Concat => "||",
+            Builtin::Sub => "-",
+            Builtin::Div => "/",
+            Builtin::Xor => "^",
+        }
     }
+}
 
-    plus(r[l - 1], &r[..l - 1])
-        .into_iter()
-        .map(|v| v * n)
-        .chain(mul(r[l - 1], &r[..l - 1]).into_iter().map(|v| v * n))
-        .collect::<Vec<_>>()
+impl Operator for Builtin {
+    fn symbol(&self) -> &str {
+        self.name()
+    }
+
+    fn apply(&self, &lhs: &Num, &rhs: &Num) -> Option<Num> {
+        match self {
+            Builtin::Add => lhs.checked_add(rhs),
+            Builtin::Mul => lhs.checked_mul(rhs),
+            Builtin::Concat => cc(lhs, rhs),
+            Builtin::Sub => lhs.checked_sub(rhs),
+            Builtin::Div => (rhs != 0 && lhs.is_multiple_of(rhs)).then(|| lhs / rhs),
+            Builtin::Xor => Some(lhs ^ rhs),
+        }
+    }
+
+    fn invert(&self, &target: &Num, &rhs: &Num) -> Inverse {
+        match self {
+            Builtin::Add => target.checked_sub(rhs).into(),
+            Builtin::Mul if rhs == 0 => match target {
+                0 => Inverse::Unknown,
+                _ => Inverse::Impossible,
+            },
+            Builtin::Mul => target.is_multiple_of(rhs).then(|| target / rhs).into(),
+            Builtin::Concat => {
+                // A target below the factor cannot end with all of rhs's digits.
+                let Some(factor) = factor(rhs) else {
+                    return Inverse::Impossible;
+                };
+                target
+                    .checked_sub(rhs)
+                    .filter(|v| v.is_multiple_of(factor))
+                    .map(|v| v / factor)
+                    .into()
+            }
+            Builtin::Sub => target.checked_add(rhs).into(),
+            Builtin::Div if rhs == 0 => Inverse::Impossible,
+            Builtin::Div => target.checked_mul(rhs).into(),
+            Builtin::Xor => Inverse::Exact(target ^ rhs),
+        }
+    }
 }
 
-fn filter(equations: &[Equation]) -> Vec<&Equation> {
-    equations
-        .par_iter()
-        .filter(|e| {
-            let collect = plus(0, &e.1);
+impl Operator<BigUint> for Builtin {
+    fn symbol(&self) -> &str {
+        self.name()
+    }
 
-            collect.contains(&e.0)
-        })
-        .collect()
+    fn apply(&self, lhs: &BigUint, rhs: &BigUint) -> Option<BigUint> {
+        let zero = BigUint::ZERO;
+
+        match self {
+            Builtin::Add => Some(lhs + rhs),
+            Builtin::Mul => Some(lhs * rhs),
+            Builtin::Concat => Some(lhs * big_factor(rhs) + rhs),
+            Builtin::Sub => (lhs >= rhs).then(|| lhs - rhs),
+            Builtin::Div => (*rhs != zero && lhs % rhs == zero).then(|| lhs / rhs),
+            Builtin::Xor => Some(lhs ^ rhs),
+        }
+    }
+
+    fn invert(&self, target: &BigUint, rhs: &BigUint) -> Inverse<BigUint> {
+        let zero = BigUint::ZERO;
+
+        match self {
+            Builtin::Add => (target >= rhs).then(|| target - rhs).into(),
+            Builtin::Mul if *rhs == zero && *target == zero => Inverse::Unknown,
+            Builtin::Mul if *rhs == zero => Inverse::Impossible,
+            Builtin::Mul => (target % rhs == zero).then(|| target / rhs).into(),
+            Builtin::Concat => {
+                let factor = big_factor(rhs);
+                (target >= rhs && (target - rhs) % &factor == zero)
+                    .then(|| (target - rhs) / &factor)
+                    .into()
+            }
+            Builtin::Sub => Inverse::Exact(target + rhs),
+            Builtin::Div if *rhs == zero => Inverse::Impossible,
+            Builtin::Div => Inverse::Exact(target * rhs),
+            Builtin::Xor => Inverse::Exact(target ^ rhs),
+        }
+    }
 }
 
-#[aoc(day07, part1)]
-pub fn solve_part1(input: &[Equation]) -> Result<Num> {
-    Ok(filter(input).into_iter().map(|e| e.0).sum())
+pub struct Custom<F> {
+    pub symbol: String,
+    pub apply: F,
 }
 
-fn plus2(n: Num, r: &[Num]) -> Vec<Num> {
-    let l = r.len();
-    if l == 1 {
-        return vec![n + r[0]];
+impl<N, F: Fn(&N, &N) -> Option<N> + Sync> Operator<N> for Custom<F> {
+    fn symbol(&self) -> &str {
+        &self.symbol
     }
 
-    plus2(r[l - 1], &r[..l - 1])
-        .into_iter()
-        .map(|v| v + n)
-        .chain(mul2(r[l - 1], &r[..l - 1]).into_iter().map(|v| v + n))
-        .chain(concat(r[l - 1], &r[..l - 1]).into_iter().map(|v| v + n))
-        .collect::<Vec<_>>()
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N> {
+        (self.apply)(lhs, rhs)
+    }
 }
 
-fn mul2(n: Num, r: &[Num]) -> Vec<Num> {
-    let l = r.len();
-    if l == 1 {
-        return vec![n * r[0]];
+type Assignment = Vec<usize>;
+
+// Remembers (position, value) states that led to no match. A state is only cloned in
+// once it fails a second time, so the one-off states of long chains cost a hash
+// rather than a copy of a possibly huge number.
+struct DeadEnds<N> {
+    hasher: RandomState,
+    failed_once: HashSet<(usize, u64)>,
+    dead: HashMap<usize, HashSet<N>>,
+}
+
+impl<N: Clone + Eq + Hash> DeadEnds<N> {
+    fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            failed_once: HashSet::new(),
+            dead: HashMap::new(),
+        }
     }
 
-    plus2(r[l - 1], &r[..l - 1])
-        .into_iter()
-        .map(|v| v * n)
-        .chain(mul2(r[l - 1], &r[..l - 1]).into_iter().map(|v| v * n))
-        .chain(concat(r[l - 1], &r[..l - 1]).into_iter().map(|v| v * n))
-        .collect::<Vec<_>>()
+    fn contains(&self, idx: usize, value: &N) -> bool {
+        self.dead.get(&idx).is_some_and(|d| d.contains(value))
+    }
+
+    fn insert(&mut self, idx: usize, value: &N) {
+        if !self.failed_once.insert((idx, self.hasher.hash_one(value))) {
+            self.dead.entry(idx).or_default().insert(value.clone());
+        }
+    }
 }
 
-fn cc(a: Num, b: Num) -> Num {
-    let digits = b.ilog10() + 1;
-    let factor = 10u64.pow(digits);
-    a * factor + b
+struct Forward<'a, N> {
+    ops: &'a [&'a dyn Operator<N>],
+    values: &'a [N],
+    accept: &'a dyn Fn(&N) -> bool,
+    dead: DeadEnds<N>,
 }
 
-fn concat(n: Num, r: &[Num]) -> Vec<Num> {
-    let l = r.len();
-    if l == 1 {
-        return vec![cc(r[0], n)];
+impl<N: Clone + Eq + Hash> Forward<'_, N> {
+    // Evaluates left to right depth first and hands every accepted assignment to
+    // `visit` as soon as it is found. Prefix values that led to no accepted result
+    // are remembered, so equal intermediate values are explored at most twice.
+    // Returns (stopped, matched).
+    fn walk(
+        &mut self,
+        idx: usize,
+        acc: N,
+        prefix: &mut Assignment,
+        visit: &mut dyn FnMut(Assignment) -> bool,
+    ) -> (bool, bool) {
+        let Some(value) = self.values.get(idx) else {
+            let matched = (self.accept)(&acc);
+            return (matched && visit(prefix.clone()), matched);
+        };
+        if self.dead.contains(idx, &acc) {
+            return (false, false);
+        }
+
+        let mut matched = false;
+        for (i, op) in self.ops.iter().enumerate() {
+            let Some(next) = op.apply(&acc, value) else {
+                continue;
+            };
+
+            prefix.push(i);
+            let (stop, m) = self.walk(idx + 1, next, prefix, visit);
+            prefix.pop();
+
+            matched |= m;
+            if stop {
+                return (true, true);
+            }
+        }
+
+        if !matched {
+            self.dead.insert(idx, &acc);
+        }
+        (false, matched)
     }
+}
 
-    plus2(r[l - 1], &r[..l - 1])
-        .into_iter()
-        .map(|v| cc(v, n))
-        .chain(mul2(r[l - 1], &r[..l - 1]).into_iter().map(|v| cc(v, n)))
-        .chain(concat(r[l - 1], &r[..l - 1]).into_iter().map(|v| cc(v, n)))
-        .collect::<Vec<_>>()
+fn forward<N: Clone + Eq + Hash>(
+    ops: &[&dyn Operator<N>],
+    values: &[N],
+    accept: &dyn Fn(&N) -> bool,
+    visit: &mut dyn FnMut(Assignment) -> bool,
+) -> (bool, bool) {
+    let Some((first, rest)) = values.split_first() else {
+        return (false, false);
+    };
+
+    let mut forward = Forward {
+        ops,
+        values: rest,
+        accept,
+        dead: DeadEnds::new(),
+    };
+    forward.walk(0, first.clone(), &mut vec![], visit)
+}
+
+fn search<N: Clone + Eq + Hash>(
+    ops: &[&dyn Operator<N>],
+    target: &N,
+    values: &[N],
+    visit: &mut dyn FnMut(Assignment) -> bool,
+) -> bool {
+    backward(
+        ops,
+        target,
+        values,
+        &mut vec![],
+        &mut DeadEnds::new(),
+        visit,
+    )
+    .0
 }
 
-fn filter2(equations: &[Equation]) -> Vec<&Equation> {
+// Like `Forward::walk`, returns (stopped, matched). Targets that matched nothing with
+// the same number of values left are skipped when another branch reaches them again.
+fn backward<N: Clone + Eq + Hash>(
+    ops: &[&dyn Operator<N>],
+    target: &N,
+    values: &[N],
+    suffix: &mut Assignment,
+    dead: &mut DeadEnds<N>,
+    visit: &mut dyn FnMut(Assignment) -> bool,
+) -> (bool, bool) {
+    let Some((last, rest)) = values.split_last() else {
+        return (false, false);
+    };
+    if rest.is_empty() {
+        let matched = last == target;
+        return (
+            matched && visit(suffix.iter().rev().copied().collect()),
+            matched,
+        );
+    }
+    if dead.contains(values.len(), target) {
+        return (false, false);
+    }
+
+    let mut matched = false;
+    for (i, op) in ops.iter().enumerate() {
+        suffix.push(i);
+        let (stop, m) = match op.invert(target, last) {
+            Inverse::Impossible => (false, false),
+            Inverse::Exact(lhs) => backward(ops, &lhs, rest, suffix, dead, visit),
+            Inverse::Unknown => {
+                let tail = suffix.iter().rev().copied().collect::<Vec<_>>();
+                forward(
+                    ops,
+                    rest,
+                    &|v| op.apply(v, last).as_ref() == Some(target),
+                    &mut |mut prefix| {
+                        prefix.extend(&tail);
+                        visit(prefix)
+                    },
+                )
+            }
+        };
+        suffix.pop();
+
+        matched |= m;
+        if stop {
+            return (true, true);
+        }
+    }
+
+    if !matched {
+        dead.insert(values.len(), target);
+    }
+    (false, matched)
+}
+
+pub fn first_solution<N: Clone + Eq + Hash>(
+    equation: &Equation<N>,
+    ops: &[&dyn Operator<N>],
+) -> Option<Assignment> {
+    let mut found = None;
+    search(ops, &equation.0, &equation.1, &mut |a| {
+        found = Some(a);
+        true
+    });
+    found
+}
+
+pub fn solutions<N: Clone + Eq + Hash>(
+    equation: &Equation<N>,
+    ops: &[&dyn Operator<N>],
+) -> Vec<Assignment> {
+    let mut found = vec![];
+    search(ops, &equation.0, &equation.1, &mut |a| {
+        found.push(a);
+        false
+    });
+    found
+}
+
+pub fn count_solutions<N: Clone + Eq + Hash>(
+    equation: &Equation<N>,
+    ops: &[&dyn Operator<N>],
+) -> usize {
+    let mut count = 0;
+    search(ops, &equation.0, &equation.1, &mut |_| {
+        count += 1;
+        false
+    });
+    count
+}
+
+pub fn render<N: Display>(
+    equation: &Equation<N>,
+    ops: &[&dyn Operator<N>],
+    assignment: &[usize],
+) -> String {
+    let rhs = equation
+        .1
+        .iter()
+        .map(|v| v.to_string())
+        .interleave(assignment.iter().map(|i| ops[*i].symbol().to_string()))
+        .join(" ");
+
+    format!("{} = {}", equation.0, rhs)
+}
+
+pub fn solvable<N: Clone + Eq + Hash>(equation: &Equation<N>, ops: &[&dyn Operator<N>]) -> bool {
+    first_solution(equation, ops).is_some()
+}
+
+pub fn calibrate(equations: &[Equation], ops: &[&dyn Operator]) -> Result<Num> {
     equations
         .par_iter()
-        .filter(|e| {
-            let collect = plus(0, &e.1);
+        .filter(|e| solvable(e, ops))
+        .map(|e| e.0)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .try_fold(0u64, |acc, v| acc.checked_add(v))
+        .ok_or(GenericError)
+        .context("Calibration total overflow")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    #[default]
+    Prune,
+    BigInt,
+}
 
-            !collect.contains(&e.0)
+pub fn calibrate_with(
+    equations: &[Equation<BigUint>],
+    ops: &[Builtin],
+    overflow: Overflow,
+) -> BigUint {
+    equations
+        .par_iter()
+        .filter(|e| match overflow {
+            Overflow::Prune => {
+                let ops = ops.iter().map(|o| o as &dyn Operator).collect::<Vec<_>>();
+                let narrow = || -> Option<Equation> {
+                    Some((
+                        u64::try_from(&e.0).ok()?,
+                        e.1.iter()
+                            .map(|v| u64::try_from(v).ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    ))
+                };
+                narrow().is_some_and(|e| solvable(&e, &ops))
+            }
+            Overflow::BigInt => {
+                let ops = ops
+                    .iter()
+                    .map(|o| o as &dyn Operator<BigUint>)
+                    .collect::<Vec<_>>();
+                solvable(e, &ops)
+            }
         })
-        .collect()
+        .map(|e| e.0.clone())
+        .sum()
+}
+
+#[aoc(day07, part1)]
+pub fn solve_part1(input: &[Equation]) -> Result<Num> {
+    calibrate(input, &[&Builtin::Add, &Builtin::Mul])
 }
 
 #[aoc(day07, part2)]
 pub fn solve_part2(input: &[Equation]) -> Result<Num> {
-    let sum1 = filter(input).into_iter().map(|e| e.0).sum::<Num>();
-    Ok(filter2(input)
-        .par_iter()
-        .filter(|e| {
-            let collect = plus2(0, &e.1);
-            collect.contains(&e.0)
-        })
-        .map(|e| e.0)
-        .sum::<Num>() + sum1)
+    calibrate(input, &[&Builtin::Add, &Builtin::Mul, &Builtin::Concat])
 }
 
 #[cfg(test)]
@@ -165,12 +514,12 @@ mod test {
 
     #[test]
     fn cc1() {
-        assert_eq!(156, cc(15, 6));
+        assert_eq!(Some(156), cc(15, 6));
     }
 
     #[test]
     fn cc2() {
-        assert_eq!(615, cc(6, 15));
+        assert_eq!(Some(615), cc(6, 15));
     }
 
     #[test]
@@ -178,7 +527,7 @@ mod test {
         for i in 0..1000 {
             for j in 1..1000 {
                 let correct = i * 10u64.pow(j.to_string().len() as u32) + j;
-                assert_eq!(correct, cc(i, j));
+                assert_eq!(Some(correct), cc(i, j));
             }
         }
     }
@@ -194,4 +543,132 @@ mod test {
         let data = input_generator(input())?;
         Ok(assert_eq!(11387, solve_part2(&data)?))
     }
+
+    #[test]
+    fn operator_sets() -> Result<()> {
+        let xor_like = Custom {
+            symbol: "|".to_string(),
+            apply: |a: &Num, b: &Num| Some(a | b),
+        };
+
+        assert!(solvable(&(5, vec![7, 2]), &[&Builtin::Sub]));
+        assert!(solvable(&(6, vec![24, 2, 2]), &[&Builtin::Div]));
+        assert!(!solvable(&(6, vec![25, 2, 2]), &[&Builtin::Div]));
+        assert!(solvable(&(3, vec![5, 6]), &[&Builtin::Xor]));
+        assert!(solvable(&(7, vec![5, 6, 0]), &[&xor_like, &Builtin::Mul]));
+        assert!(solvable(&(0, vec![5, 6, 0]), &[&xor_like, &Builtin::Mul]));
+        Ok(assert!(solvable(
+            &(100, vec![1, 0, 0]),
+            &[&Builtin::Concat]
+        )))
+    }
+
+    #[test]
+    fn long_equation() -> Result<()> {
+        let ops: [&dyn Operator; 3] = [&Builtin::Add, &Builtin::Mul, &Builtin::Concat];
+
+        // Adding every 1 falls one short, so the search has to back out of the
+        // all-Add branch and find the single Concat.
+        let first = first_solution(&(61, vec![1; 60]), &ops).ok_or(GenericError)?;
+        assert_eq!(1, first.iter().filter(|op| **op == 2).count());
+
+        // Everything built from 2s is even, so a near-miss odd target rules out every
+        // branch and only the memoised dead ends keep this from going exponential.
+        assert!(!solvable(&(121, vec![2; 60]), &ops));
+        Ok(assert!(solvable(&(122, vec![2; 60]), &ops)))
+    }
+
+    #[test]
+    fn assignments() -> Result<()> {
+        let ops: [&dyn Operator; 3] = [&Builtin::Add, &Builtin::Mul, &Builtin::Concat];
+        let data = input_generator(input())?;
+
+        let first = first_solution(&data[8], &ops).ok_or(GenericError)?;
+        assert_eq!("292 = 11 + 6 * 16 + 20", render(&data[8], &ops, &first));
+        assert_eq!(None, first_solution(&data[2], &ops));
+
+        let all = solutions(&data[1], &ops)
+            .iter()
+            .map(|a| render(&data[1], &ops, a))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["3267 = 81 * 40 + 27", "3267 = 81 + 40 * 27"], all);
+        assert_eq!(2, count_solutions(&data[1], &ops));
+        Ok(assert_eq!(3, count_solutions(&(0, vec![5, 6, 0]), &ops)))
+    }
+
+    #[test]
+    fn overflow() -> Result<()> {
+        let ops = [Builtin::Add, Builtin::Mul, Builtin::Sub];
+        let input = format!("{}: {} 10 15", u64::MAX - 5, u64::MAX);
+        let data = big_input_generator(&input)?;
+
+        assert_eq!(BigUint::ZERO, calibrate_with(&data, &ops, Overflow::Prune));
+        Ok(assert_eq!(
+            BigUint::from(u64::MAX - 5),
+            calibrate_with(&data, &ops, Overflow::BigInt)
+        ))
+    }
+
+    #[test]
+    fn overflow_concat_factor() -> Result<()> {
+        let ops: [&dyn Operator; 3] = [&Builtin::Add, &Builtin::Mul, &Builtin::Concat];
+        let big = 10_000_000_000_000_000_000;
+        assert!(!solvable(&(5, vec![1, big]), &ops));
+        assert!(!solvable(
+            &(17766279631452241920, vec![1, big]),
+            &[&Builtin::Concat]
+        ));
+        assert_eq!(None, Builtin::Concat.apply(&1, &big));
+        assert_eq!(Inverse::Impossible, Builtin::Concat.invert(&big, &big));
+
+        let data = big_input_generator(&format!("5: 1 {big}"))?;
+        let ops = [Builtin::Add, Builtin::Mul, Builtin::Concat];
+        assert_eq!(BigUint::ZERO, calibrate_with(&data, &ops, Overflow::Prune));
+
+        let data = input_generator(&format!("{0}: {0}\n{0}: {0}", u64::MAX))?;
+        Ok(assert!(calibrate(&data, &[&Builtin::Add]).is_err()))
+    }
+
+    #[test]
+    fn long_big_equations() -> Result<()> {
+        let ops = [Builtin::Add, Builtin::Mul, Builtin::Concat];
+        let values = (0..300)
+            .map(|i| 999_999_999_999_u64 - i)
+            .collect::<Vec<_>>();
+
+        let concatenated = values.iter().map(|v| v.to_string()).join("");
+        let product = values
+            .iter()
+            .map(|v| BigUint::from(*v))
+            .product::<BigUint>()
+            + 1u32;
+        let rhs = values.iter().join(" ");
+        let input = format!("{concatenated}: {rhs}\n{product}: {rhs} 1");
+        let data = big_input_generator(&input)?;
+
+        assert_eq!(BigUint::ZERO, calibrate_with(&data, &ops, Overflow::Prune));
+        Ok(assert_eq!(
+            concatenated.parse::<BigUint>()? + &product,
+            calibrate_with(&data, &ops, Overflow::BigInt)
+        ))
+    }
+
+    #[test]
+    fn unknown_inverse_stays_lazy() {
+        let ops: [&dyn Operator; 3] = [&Builtin::Add, &Builtin::Mul, &Builtin::Concat];
+        let mut values = vec![1; 24];
+        values.push(0);
+        assert!(solvable(&(0, values.clone()), &ops));
+        let mut first = vec![0; 23];
+        first.push(1);
+        assert_eq!(Some(first), first_solution(&(0, values), &ops));
+
+        let max = Custom {
+            symbol: "max".to_string(),
+            apply: |a: &Num, b: &Num| Some(*a.max(b)),
+        };
+        let ops: [&dyn Operator; 2] = [&Builtin::Add, &max];
+        assert!(!solvable(&(1000, vec![1; 40]), &ops));
+        assert!(solvable(&(39, vec![1; 40]), &ops));
+    }
 }
@@ -1,9 +1,11 @@
 use std::collections::{HashMap, HashSet};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 
+use crate::utils::AocError::*;
+
 type Base = i32;
 type Coords = (Base, Base);
 type Map = HashMap<Coords, char>;
@@ -26,97 +28,203 @@ fn is_on_map(map: &Map, coords: &Coords) -> bool {
     map.get(coords).is_some()
 }
 
-#[aoc(day08, part1)]
-pub fn solve_part1(input: &Map) -> Result<usize> {
-    let frequencies = input
-        .iter()
-        .filter(|&v| *v.1 != '.')
-        .map(|v| v.1)
-        .collect::<HashSet<_>>();
+fn gcd(a: Base, b: Base) -> Base {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
 
-    let sum = frequencies
-        .into_iter()
-        .flat_map(|freq| {
-            let positions = input.iter().filter(|&a| a.1 == freq).map(|p| p.0);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    Harmonic(Base),
+    AllHarmonics,
+    Internal(Base, Base),
+    Line,
+}
 
-            positions
-                .combinations(2)
-                .flat_map(|c| {
-                    if c.len() < 2 {
-                        return vec![];
-                    }
+fn walk(map: &Map, from: Coords, step: Coords) -> Vec<Coords> {
+    let mut points = vec![];
+    let mut p = from;
+    while is_on_map(map, &p) {
+        points.push(p);
+        p = (p.0 + step.0, p.1 + step.1);
+    }
+    points
+}
 
-                    // a = (3, 4)
-                    // b = (1, 2)
+fn pair_antinodes(map: &Map, a: Coords, b: Coords, rule: Rule) -> Vec<Coords> {
+    let diff = (a.0 - b.0, a.1 - b.1);
 
-                    // x = (5, 6)
-                    // y = (-1, 0)
+    let points = match rule {
+        Rule::Harmonic(k) => vec![
+            (a.0 + k * diff.0, a.1 + k * diff.1),
+            (b.0 - k * diff.0, b.1 - k * diff.1),
+        ],
+        Rule::AllHarmonics => {
+            let mut points = walk(map, a, diff);
+            points.append(&mut walk(map, b, (-diff.0, -diff.1)));
+            points
+        }
+        Rule::Internal(m, n) => [(m, n), (n, m)]
+            .into_iter()
+            .filter(|(m, n)| m + n != 0)
+            .filter_map(|(m, n)| {
+                let x = (n * a.0 + m * b.0, n * a.1 + m * b.1);
+                let total = m + n;
+                (x.0 % total == 0 && x.1 % total == 0).then_some((x.0 / total, x.1 / total))
+            })
+            .collect(),
+        Rule::Line => {
+            let g = gcd(diff.0, diff.1).max(1);
+            let step = (diff.0 / g, diff.1 / g);
+            let mut points = walk(map, a, step);
+            points.append(&mut walk(map, a, (-step.0, -step.1)));
+            points
+        }
+    };
 
-                    let a = c[0];
-                    let b = c[1];
+    points.into_iter().filter(|p| is_on_map(map, p)).collect()
+}
 
-                    let diff = (a.0 - b.0, a.1 - b.1);
-                    let x = (a.0 + diff.0, a.1 + diff.1);
-                    let y = (b.0 - diff.0, b.1 - diff.1);
+fn antennas(map: &Map) -> HashMap<char, Vec<Coords>> {
+    let mut antennas: HashMap<char, Vec<Coords>> = HashMap::new();
+    for (p, c) in map.iter().filter(|v| *v.1 != '.') {
+        antennas.entry(*c).or_default().push(*p);
+    }
+    antennas
+}
 
-                    vec![x, y]
-                        .into_iter()
-                        .filter(|e| is_on_map(input, e))
-                        .collect::<Vec<_>>()
-                })
+pub fn antinodes(map: &Map, rules: &[Rule]) -> HashSet<Coords> {
+    antennas(map)
+        .values()
+        .flat_map(|positions| {
+            positions
+                .iter()
+                .tuple_combinations()
+                .flat_map(|(a, b)| rules.iter().flat_map(|r| pair_antinodes(map, *a, *b, *r)))
                 .collect::<Vec<_>>()
         })
-        .collect::<HashSet<_>>()
-        .len();
+        .collect()
+}
 
-    Ok(sum)
+#[derive(Debug, Clone)]
+pub struct AntennaMap {
+    map: Map,
+    rules: Vec<Rule>,
+    antennas: HashMap<char, Vec<Coords>>,
+    antinodes: HashMap<Coords, usize>,
 }
 
-#[aoc(day08, part2)]
-pub fn solve_part2(input: &Map) -> Result<usize> {
-    let antennas = input.iter().filter(|&v| *v.1 != '.').collect::<Vec<_>>();
-    let frequencies = antennas.iter().map(|v| v.1).collect::<HashSet<_>>();
-    let antenna_positions = antennas.into_iter().map(|v| *v.0);
+impl AntennaMap {
+    pub fn new(map: &Map, rules: &[Rule]) -> Self {
+        let mut editor = AntennaMap {
+            map: map.keys().map(|p| (*p, '.')).collect(),
+            rules: rules.to_vec(),
+            antennas: HashMap::new(),
+            antinodes: HashMap::new(),
+        };
 
-    let sum = frequencies
-        .into_iter()
-        .flat_map(|freq| {
-            let positions = input.iter().filter(|&a| a.1 == freq).map(|p| p.0);
+        for (p, c) in map.iter().filter(|v| *v.1 != '.') {
+            editor.insert(*p, *c);
+        }
 
-            positions
-                .combinations(2)
-                .flat_map(|c| {
-                    if c.len() < 2 {
-                        return vec![];
-                    }
+        editor
+    }
 
-                    let a = c[0];
-                    let b = c[1];
+    pub fn count(&self) -> usize {
+        self.antinodes.len()
+    }
+
+    pub fn antinodes(&self) -> HashSet<Coords> {
+        self.antinodes.keys().copied().collect()
+    }
 
-                    let diff = (a.0 - b.0, a.1 - b.1);
+    pub fn add(&mut self, pos: Coords, frequency: char) -> Result<usize> {
+        match self.map.get(&pos) {
+            Some('.') if frequency != '.' => {}
+            Some('.') => Err(GenericError).context("Not a frequency")?,
+            Some(_) => Err(GenericError).context("Position is occupied")?,
+            None => Err(GenericError).context("Position is not on the map")?,
+        }
 
-                    let mut antinodes = vec![];
-                    let mut p = (a.0 + diff.0, a.1 + diff.1);
-                    while is_on_map(input, &p) {
-                        antinodes.push(p);
-                        p = (p.0 + diff.0, p.1 + diff.1);
-                    }
+        self.insert(pos, frequency);
+        Ok(self.count())
+    }
+
+    pub fn remove(&mut self, pos: Coords) -> Result<usize> {
+        let frequency = match self.map.get(&pos) {
+            Some('.') | None => Err(GenericError).context("No antenna at position")?,
+            Some(c) => *c,
+        };
+
+        self.update(pos, frequency, false);
+        self.map.insert(pos, '.');
+        if let Some(positions) = self.antennas.get_mut(&frequency) {
+            positions.retain(|p| *p != pos);
+        }
+
+        Ok(self.count())
+    }
+
+    pub fn move_antenna(&mut self, from: Coords, to: Coords) -> Result<usize> {
+        let frequency = *self
+            .map
+            .get(&from)
+            .filter(|c| **c != '.')
+            .ok_or(GenericError)
+            .context("No antenna at position")?;
+
+        if self.map.get(&to) != Some(&'.') {
+            Err(GenericError).context("Target position is not free")?;
+        }
+
+        self.remove(from)?;
+        self.add(to, frequency)
+    }
+
+    fn insert(&mut self, pos: Coords, frequency: char) {
+        self.update(pos, frequency, true);
+        self.map.insert(pos, frequency);
+        self.antennas.entry(frequency).or_default().push(pos);
+    }
+
+    fn update(&mut self, pos: Coords, frequency: char, add: bool) {
+        let others = self
+            .antennas
+            .get(&frequency)
+            .into_iter()
+            .flatten()
+            .filter(|p| **p != pos);
 
-                    let mut q = (b.0 - diff.0, b.1 - diff.1);
-                    while is_on_map(input, &q) {
-                        antinodes.push(q);
-                        q = (q.0 - diff.0, q.1 - diff.1);
+        for other in others {
+            let (a, b) = (pos.min(*other), pos.max(*other));
+            for rule in &self.rules {
+                for p in pair_antinodes(&self.map, a, b, *rule) {
+                    let count = self.antinodes.entry(p).or_default();
+                    if add {
+                        *count += 1;
+                    } else {
+                        *count -= 1;
+                        if *count == 0 {
+                            self.antinodes.remove(&p);
+                        }
                     }
+                }
+            }
+        }
+    }
+}
 
-                    antinodes
-                })
-                .collect::<Vec<_>>()
-        })
-        .chain(antenna_positions)
-        .collect::<HashSet<_>>()
-        .len();
+#[aoc(day08, part1)]
+pub fn solve_part1(input: &Map) -> Result<usize> {
+    Ok(antinodes(input, &[Rule::Harmonic(1)]).len())
+}
 
-    Ok(sum)
+#[aoc(day08, part2)]
+pub fn solve_part2(input: &Map) -> Result<usize> {
+    Ok(antinodes(input, &[Rule::Line]).len())
 }
 
 #[cfg(test)]
@@ -187,4 +295,63 @@ mod test {
         let data = input_generator(example2())?;
         Ok(assert_eq!(9, solve_part2(&data)?))
     }
+
+    #[test]
+    fn rules() -> Result<()> {
+        let data = input_generator(example())?;
+        assert_eq!(34, antinodes(&data, &[Rule::AllHarmonics]).len());
+
+        let data = input_generator(
+            "..........
+.a........
+..........
+..........
+..........
+..........
+..........
+.......a..
+..........
+..........",
+        )?;
+
+        assert_eq!(
+            HashSet::from([(3, 3), (5, 5)]),
+            antinodes(&data, &[Rule::Internal(1, 2)])
+        );
+        assert_eq!(HashSet::new(), antinodes(&data, &[Rule::Internal(1, 3)]));
+        assert_eq!(2, antinodes(&data, &[Rule::AllHarmonics]).len());
+        assert_eq!(10, antinodes(&data, &[Rule::Line]).len());
+        Ok(assert_eq!(
+            HashSet::from([(1, 1), (7, 7)]),
+            antinodes(&data, &[Rule::Harmonic(0), Rule::Harmonic(2)])
+        ))
+    }
+
+    #[test]
+    fn editor() -> Result<()> {
+        let data = input_generator(example())?;
+        let mut editor = AntennaMap::new(&data, &[Rule::Harmonic(1)]);
+        assert_eq!(14, editor.count());
+
+        let count = editor.add((0, 0), 'A')?;
+        let mut expected = data.clone();
+        expected.insert((0, 0), 'A');
+        assert_eq!(solve_part1(&expected)?, count);
+
+        let count = editor.move_antenna((5, 6), (10, 1))?;
+        expected.insert((5, 6), '.');
+        expected.insert((10, 1), 'A');
+        assert_eq!(solve_part1(&expected)?, count);
+        assert_eq!(
+            antinodes(&expected, &[Rule::Harmonic(1)]),
+            editor.antinodes()
+        );
+
+        assert!(editor.add((1, 8), 'b').is_err());
+        assert!(editor.remove((0, 1)).is_err());
+        editor.remove((0, 0))?;
+        editor.remove((10, 1))?;
+        editor.add((5, 6), 'A')?;
+        Ok(assert_eq!(14, editor.count()))
+    }
 }
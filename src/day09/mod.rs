@@ -1,8 +1,10 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+};
 
 use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::Itertools;
 
 use crate::utils::AocError::*;
 
@@ -44,136 +46,299 @@ pub fn input_generator(input: &str) -> Result<Vec<FileSystemEntry>> {
         .collect::<Result<Vec<_>>>()
 }
 
-fn can_be_compacted(hdd: &[FileSystemEntry]) -> bool {
-    let first_empty = hdd
-        .iter()
-        .find_position(|v| v.is_empty())
-        .map(|v| v.0)
-        .unwrap_or(hdd.len());
-    let last_file = hdd.len()
-        - 1
-        - hdd
-            .iter()
-            .rev()
-            .find_position(|v| v.is_file())
-            .map(|v| v.0)
-            .unwrap_or(0);
-
-    first_empty < last_file
-}
-
-fn hash_hdd(hdd: &[FileSystemEntry]) -> usize {
-    hdd.iter()
-        .fold((0_usize, 0_usize), |(index, sum), v| {
-            if let File(size, id) = v {
-                let size = *size as usize;
-                let plus = (index..index + size)
-                    .map(|i| i * *id as usize)
-                    .sum::<usize>();
-                (index + size, sum + plus)
-            } else if let Empty(size) = v {
-                (index + *size as usize, sum)
-            } else {
-                (index, sum)
-            }
-        })
-        .1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileSpan {
+    pub id: u32,
+    pub start: usize,
+    pub size: u32,
 }
 
-#[aoc(day09, part1)]
-pub fn solve_part1(input: &[FileSystemEntry]) -> Result<usize> {
-    let mut hdd = input.to_vec();
-
-    while can_be_compacted(&hdd) {
-        let first_empty = hdd
-            .clone()
-            .into_iter()
-            .find_position(|v| v.is_empty())
-            .ok_or(GenericError)
-            .context("No free space left on device")?;
-        let mut last_file = hdd
-            .clone()
-            .into_iter()
-            .rev()
-            .find_position(|v| v.is_file())
-            .ok_or(GenericError)
-            .context("No file found")?;
-        last_file.0 = hdd.len() - last_file.0 - 1;
-
-        match (first_empty.1, last_file.1) {
-            (Empty(free_space), File(file_size, id)) => match free_space.cmp(&file_size) {
-                Ordering::Less => {
-                    hdd[last_file.0] = File(file_size - free_space, id);
-                    hdd[first_empty.0] = File(free_space, id);
-                }
-                Ordering::Equal => {
-                    hdd[last_file.0] = Empty(free_space);
-                    hdd[first_empty.0] = File(free_space, id);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FreeSpan {
+    pub start: usize,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    pub files: Vec<FileSpan>,
+    pub free: Vec<FreeSpan>,
+    pub len: usize,
+}
+
+impl Layout {
+    pub fn new(hdd: &[FileSystemEntry]) -> Self {
+        let mut layout = Layout::default();
+        let mut start = 0;
+
+        for entry in hdd {
+            match *entry {
+                File(size, id) => {
+                    layout.files.push(FileSpan { id, start, size });
+                    start += size as usize;
                 }
-                Ordering::Greater => {
-                    hdd.remove(last_file.0);
-                    hdd[first_empty.0] = Empty(free_space - file_size);
-                    hdd.insert(first_empty.0, File(file_size, id));
+                Empty(size) => {
+                    // Zero-sized files leave neighbouring gaps contiguous on disk.
+                    match layout.free.last_mut() {
+                        Some(last) if last.start + last.size as usize == start => last.size += size,
+                        _ if size > 0 => layout.free.push(FreeSpan { start, size }),
+                        _ => {}
+                    }
+                    start += size as usize;
                 }
-            },
-            _ => unimplemented!(),
+            }
         }
+
+        layout.len = start;
+        layout
     }
+}
 
-    Ok(hash_hdd(&hdd))
+pub fn checksum(files: &[FileSpan]) -> usize {
+    files
+        .iter()
+        .map(|f| {
+            let size = f.size as usize;
+            f.id as usize * (size * f.start + size * size.saturating_sub(1) / 2)
+        })
+        .sum()
 }
 
-#[aoc(day09, part2)]
-pub fn solve_part2(input: &[FileSystemEntry]) -> Result<usize> {
-    let mut hdd = input.to_vec();
-    let max_file_id = *input
+pub fn compact_blocks(layout: &Layout) -> Vec<FileSpan> {
+    let mut files = layout.files.clone();
+    let mut free = layout.free.iter().copied().collect::<VecDeque<_>>();
+    let mut moved = vec![];
+
+    while let Some(mut file) = files.pop() {
+        while file.size > 0 {
+            let Some(span) = free.front_mut().filter(|s| s.start < file.start) else {
+                break;
+            };
+
+            let n = span.size.min(file.size);
+            moved.push(FileSpan {
+                id: file.id,
+                start: span.start,
+                size: n,
+            });
+            span.start += n as usize;
+            span.size -= n;
+            file.size -= n;
+
+            if span.size == 0 {
+                free.pop_front();
+            }
+        }
+
+        if file.size > 0 {
+            files.push(file);
+            break;
+        }
+    }
+
+    files.append(&mut moved);
+    files
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    FirstFit,
+    BestFit,
+    WorstFit,
+    NextFit,
+    Defragment,
+}
+
+pub fn compact_files(layout: &Layout) -> Vec<FileSpan> {
+    compact(layout, Strategy::FirstFit)
+}
+
+pub fn compact(layout: &Layout, strategy: Strategy) -> Vec<FileSpan> {
+    match strategy {
+        Strategy::NextFit => compact_next_fit(layout),
+        Strategy::Defragment => defragment(layout),
+        _ => compact_with_heaps(layout, strategy),
+    }
+}
+
+fn compact_with_heaps(layout: &Layout, strategy: Strategy) -> Vec<FileSpan> {
+    let mut heaps: BTreeMap<u32, BinaryHeap<Reverse<usize>>> = BTreeMap::new();
+    for span in &layout.free {
+        heaps
+            .entry(span.size)
+            .or_default()
+            .push(Reverse(span.start));
+    }
+
+    let mut files = layout.files.clone();
+    files.sort_by_key(|f| Reverse(f.id));
+
+    for file in files.iter_mut() {
+        let mut candidates = heaps
+            .range(file.size..)
+            .filter_map(|(size, heap)| heap.peek().map(|Reverse(start)| (*start, *size)))
+            .filter(|(start, _)| *start < file.start);
+
+        let candidate = match strategy {
+            Strategy::BestFit => candidates.next(),
+            Strategy::WorstFit => candidates.next_back(),
+            _ => candidates.min(),
+        };
+
+        if let Some((start, size)) = candidate {
+            heaps.get_mut(&size).and_then(|h| h.pop());
+            file.start = start;
+            if size > file.size {
+                heaps
+                    .entry(size - file.size)
+                    .or_default()
+                    .push(Reverse(start + file.size as usize));
+            }
+        }
+    }
+
+    files
+}
+
+fn compact_next_fit(layout: &Layout) -> Vec<FileSpan> {
+    let mut free = layout
+        .free
         .iter()
-        .filter_map(|v| if let File(_, id) = v { Some(id) } else { None })
-        .max()
-        .ok_or(GenericError)
-        .context("Empty disk")?;
-
-    for id in (0..=max_file_id).rev() {
-        let file = hdd
-            .clone()
-            .into_iter()
-            .find_position(|f| if let File(_, i) = f { *i == id } else { false })
-            .ok_or(GenericError)
-            .context("Could not find specific file")?;
-
-        match file.1 {
-            File(file_size, id) => {
-                let first_empty = hdd.clone().into_iter().find_position(|v| {
-                    if let Empty(u) = v {
-                        *u >= file_size
-                    } else {
-                        false
-                    }
-                });
-
-                match first_empty {
-                    None => continue,
-                    Some((idx, Empty(size))) => {
-                        if idx > file.0 {
-                            continue;
-                        }
-                        if size > file_size {
-                            hdd[idx] = Empty(size - file_size);
-                            hdd[file.0] = Empty(file_size);
-                            hdd.insert(idx, File(file_size, id));
-                        } else {
-                            hdd[idx] = File(file_size, id);
-                            hdd[file.0] = Empty(file_size);
-                        }
-                    }
-                    _ => unimplemented!(),
-                }
+        .map(|s| (s.start, s.size))
+        .collect::<BTreeMap<_, _>>();
+    let mut cursor = 0;
+
+    let mut files = layout.files.clone();
+    files.sort_by_key(|f| Reverse(f.id));
+
+    for file in files.iter_mut() {
+        let fits = |(start, size): (&usize, &u32)| *size >= file.size && *start < file.start;
+        let candidate = free
+            .range(cursor..)
+            .find(|v| fits(*v))
+            .or_else(|| free.range(..cursor).find(|v| fits(*v)))
+            .map(|(start, size)| (*start, *size));
+
+        if let Some((start, size)) = candidate {
+            free.remove(&start);
+            file.start = start;
+            cursor = start + file.size as usize;
+            if size > file.size {
+                free.insert(cursor, size - file.size);
+            }
+        }
+    }
+
+    files
+}
+
+fn defragment(layout: &Layout) -> Vec<FileSpan> {
+    let mut files = layout.files.clone();
+    files.sort_by_key(|f| f.start);
+
+    let mut start = 0;
+    for file in files.iter_mut() {
+        file.start = start;
+        start += file.size as usize;
+    }
+
+    files
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fragmentation {
+    pub extent: usize,
+    pub free_blocks: usize,
+    pub free_spans: usize,
+    pub largest_free_span: usize,
+    pub split_files: usize,
+    pub external: f64,
+}
+
+pub fn fragmentation(files: &[FileSpan]) -> Fragmentation {
+    let mut spans = files.iter().filter(|f| f.size > 0).collect::<Vec<_>>();
+    spans.sort_by_key(|f| f.start);
+
+    let mut end = 0;
+    let mut gaps = vec![];
+    for f in &spans {
+        if f.start > end {
+            gaps.push(f.start - end);
+        }
+        end = end.max(f.start + f.size as usize);
+    }
+
+    let free_blocks = gaps.iter().sum::<usize>();
+    let largest_free_span = gaps.iter().copied().max().unwrap_or(0);
+    let mut pieces: BTreeMap<u32, usize> = BTreeMap::new();
+    for f in &spans {
+        *pieces.entry(f.id).or_default() += 1;
+    }
+
+    Fragmentation {
+        extent: end,
+        free_blocks,
+        free_spans: gaps.len(),
+        largest_free_span,
+        split_files: pieces.values().filter(|n| **n > 1).count(),
+        external: if free_blocks == 0 {
+            0.0
+        } else {
+            1.0 - largest_free_span as f64 / free_blocks as f64
+        },
+    }
+}
+
+pub fn render_blocks(files: &[FileSpan], len: usize) -> String {
+    let mut blocks = vec!['.'; len];
+    for f in files {
+        let c = char::from_digit(f.id % 10, 10).unwrap_or('?');
+        for block in blocks.iter_mut().skip(f.start).take(f.size as usize) {
+            *block = c;
+        }
+    }
+    blocks.into_iter().collect()
+}
+
+// The dense format implies file ids by position, so only the shape of the layout survives.
+// Runs longer than 9 blocks are split using zero-sized runs of the other kind.
+pub fn render_dense(files: &[FileSpan]) -> String {
+    let mut spans = files.iter().filter(|f| f.size > 0).collect::<Vec<_>>();
+    spans.sort_by_key(|f| f.start);
+
+    let mut runs = vec![];
+    let mut end = 0;
+    for f in spans {
+        let gap = f.start - end;
+        if !runs.is_empty() || gap > 0 {
+            if runs.is_empty() {
+                runs.push(0);
             }
-            _ => unimplemented!(),
+            runs.push(gap);
+        }
+        runs.push(f.size as usize);
+        end = f.start + f.size as usize;
+    }
+
+    let mut dense = String::new();
+    for mut len in runs {
+        while len > 9 {
+            dense.push_str("90");
+            len -= 9;
         }
+        dense.push(char::from_digit(len as u32, 10).unwrap_or('?'));
     }
+    dense
+}
+
+#[aoc(day09, part1)]
+pub fn solve_part1(input: &[FileSystemEntry]) -> Result<usize> {
+    Ok(checksum(&compact_blocks(&Layout::new(input))))
+}
 
-    Ok(hash_hdd(&hdd))
+#[aoc(day09, part2)]
+pub fn solve_part2(input: &[FileSystemEntry]) -> Result<usize> {
+    Ok(checksum(&compact_files(&Layout::new(input))))
 }
 
 #[cfg(test)]
@@ -195,4 +360,142 @@ mod test {
         let data = input_generator(input())?;
         Ok(assert_eq!(2858, solve_part2(&data)?))
     }
+
+    #[test]
+    fn large_disk() -> Result<()> {
+        let input = (0..1_000_000)
+            .map(|i| char::from(b'1' + (i * 7 % 9) as u8))
+            .collect::<String>();
+        let data = input_generator(&input)?;
+        let layout = Layout::new(&data);
+
+        let mut files = compact_files(&layout);
+        files.sort_by_key(|f| f.id);
+        assert_eq!(layout.files.len(), files.len());
+        assert!(files
+            .iter()
+            .zip(&layout.files)
+            .all(|(a, b)| a.start <= b.start));
+        Ok(assert!(compact_blocks(&layout).iter().all(|f| f.size > 0)))
+    }
+
+    // Block-by-block first fit: scan from the left for every file, highest id first.
+    fn naive_first_fit(hdd: &[FileSystemEntry]) -> usize {
+        let mut blocks = hdd
+            .iter()
+            .flat_map(|e| match *e {
+                File(size, id) => vec![Some(id); size as usize],
+                Empty(size) => vec![None; size as usize],
+            })
+            .collect::<Vec<_>>();
+
+        for id in hdd
+            .iter()
+            .filter_map(|e| match e {
+                File(_, id) => Some(*id),
+                Empty(_) => None,
+            })
+            .rev()
+        {
+            let Some(start) = blocks.iter().position(|b| *b == Some(id)) else {
+                continue;
+            };
+            let size = blocks[start..]
+                .iter()
+                .take_while(|b| **b == Some(id))
+                .count();
+
+            let mut run = 0;
+            for i in 0..start {
+                run = if blocks[i].is_none() { run + 1 } else { 0 };
+                if run == size {
+                    blocks[i + 1 - size..=i].fill(Some(id));
+                    blocks[start..start + size].fill(None);
+                    break;
+                }
+            }
+        }
+
+        blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.map(|id| i * id as usize))
+            .sum()
+    }
+
+    #[test]
+    fn random_disks_match_naive() -> Result<()> {
+        let mut state = 0x2024_u64;
+        let mut random = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            char::from(b'0' + ((state >> 33) % 10) as u8)
+        };
+
+        for _ in 0..20 {
+            let input = (0..1_000).map(|_| random()).collect::<String>();
+            let data = input_generator(&input)?;
+            assert_eq!(naive_first_fit(&data), solve_part2(&data)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn strategies() -> Result<()> {
+        let data = input_generator(input())?;
+        let layout = Layout::new(&data);
+
+        let render = |strategy| render_blocks(&compact(&layout, strategy), layout.len);
+        assert_eq!(
+            "00992111777.44.333....5555.6666.....8888..",
+            render(Strategy::FirstFit)
+        );
+        assert_eq!(
+            "0099811188827773336446555566..............",
+            render_blocks(&compact_blocks(&layout), layout.len)
+        );
+        assert_eq!(
+            "0011123334455556666777888899..............",
+            render(Strategy::Defragment)
+        );
+        assert_eq!(render(Strategy::FirstFit), render(Strategy::BestFit));
+
+        // NextFit wraps around to the first gap once the cursor has passed file 2.
+        let skewed = Layout::new(&input_generator("111312142")?);
+        let render_skewed = |strategy| render_blocks(&compact(&skewed, strategy), skewed.len);
+        assert_eq!("031442..........", render_skewed(Strategy::FirstFit));
+        assert_eq!("0312...44.......", render_skewed(Strategy::BestFit));
+        assert_eq!("01.32.....44....", render_skewed(Strategy::WorstFit));
+        assert_eq!("021443..........", render_skewed(Strategy::NextFit));
+
+        let defragmented = fragmentation(&compact(&layout, Strategy::Defragment));
+        assert_eq!(0, defragmented.free_blocks);
+        assert_eq!(0.0, defragmented.external);
+
+        let first_fit = fragmentation(&compact(&layout, Strategy::FirstFit));
+        assert_eq!(40, first_fit.extent);
+        assert_eq!(5, first_fit.free_spans);
+        Ok(assert_eq!(
+            "2333133121414131402",
+            render_dense(&layout.files)
+        ))
+    }
+
+    #[test]
+    fn dense_long_runs() {
+        let files = vec![
+            FileSpan {
+                id: 0,
+                start: 3,
+                size: 12,
+            },
+            FileSpan {
+                id: 1,
+                start: 15,
+                size: 1,
+            },
+        ];
+        assert_eq!("0390301", render_dense(&files));
+    }
 }
@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
-use pathfinding::prelude::dijkstra_all;
+use itertools::Itertools;
 
 type Base = i32;
 type Coords = (Base, Base);
@@ -22,18 +22,57 @@ pub fn input_generator(input: &str) -> Result<Map> {
     Ok(map)
 }
 
-fn successors(map: &Map, node: &Coords) -> Vec<(Coords, usize)> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailConfig {
+    pub start: Base,
+    pub end: Base,
+    pub step: Base,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            end: 9,
+            step: 1,
+        }
+    }
+}
+
+impl TrailConfig {
+    fn levels(&self) -> usize {
+        let diff = self.end - self.start;
+        if self.step == 0 || diff % self.step != 0 || diff / self.step < 0 {
+            return 0;
+        }
+        (diff / self.step) as usize + 1
+    }
+
+    fn level(&self, height: Base) -> Option<usize> {
+        let diff = height - self.start;
+        if self.step == 0 || diff % self.step != 0 {
+            return None;
+        }
+        let level = diff / self.step;
+        (level >= 0 && (level as usize) < self.levels()).then_some(level as usize)
+    }
+}
+
+fn height(map: &Map, node: &Coords) -> Option<Base> {
+    Some(map.get(node)?.to_digit(10)? as Base)
+}
+
+fn successors(map: &Map, node: &Coords, step: Base) -> Vec<Coords> {
     let dirs = [(-1, 0), (0, 1), (1, 0), (0, -1)];
-    let node_value = map.get(node);
+    let node_value = height(map, node);
 
     dirs.into_iter()
         .filter_map(|d| {
-            let h = node_value?.to_digit(10)?;
+            let h = node_value?;
             let c = (node.0 + d.0, node.1 + d.1);
-            let v = map.get(&c)?;
 
-            if v.to_digit(10)? == 1 + h {
-                Some((c, 1))
+            if height(map, &c)? == h + step {
+                Some(c)
             } else {
                 None
             }
@@ -41,80 +80,292 @@ fn successors(map: &Map, node: &Coords) -> Vec<(Coords, usize)> {
         .collect::<Vec<_>>()
 }
 
-#[aoc(day10, part1)]
-pub fn solve_part1(input: &Map) -> Result<u32> {
-    let map = input;
-    let sum = map
-        .iter()
-        .filter(|(_, v)| **v == '0')
-        .map(|(c, _)| c)
-        .map(|head| dijkstra_all(head, |n| successors(map, n)))
-        .map(|p| {
-            p.iter()
-                .filter_map(|(k, _)| if map.get(k)? == &'9' { Some(1) } else { None })
-                .sum::<u32>()
-        })
-        .sum::<u32>();
-
-    Ok(sum)
+#[derive(Debug, Clone)]
+pub struct TrailMap<'a> {
+    map: &'a Map,
+    config: TrailConfig,
+    levels: Vec<Vec<Coords>>,
+    ratings: HashMap<Coords, u64>,
 }
 
-fn find_all_paths(map: &Map, start: &Coords) -> usize {
-    let mut queue = vec![(*start, vec![*start])];
-    let mut all_paths = vec![];
+impl<'a> TrailMap<'a> {
+    pub fn new(map: &'a Map, config: TrailConfig) -> Self {
+        let mut levels = vec![vec![]; config.levels()];
+        for c in map.keys() {
+            if let Some(level) = height(map, c).and_then(|h| config.level(h)) {
+                levels[level].push(*c);
+            }
+        }
+        levels.iter_mut().for_each(|l| l.sort());
+
+        let mut ratings = HashMap::new();
+        for (k, level) in levels.iter().enumerate().rev() {
+            for c in level {
+                let rating = if k + 1 == levels.len() {
+                    1
+                } else {
+                    successors(map, c, config.step)
+                        .iter()
+                        .filter_map(|n| ratings.get(n))
+                        .sum()
+                };
+                ratings.insert(*c, rating);
+            }
+        }
+
+        Self {
+            map,
+            config,
+            levels,
+            ratings,
+        }
+    }
+
+    pub fn trailheads(&self) -> &[Coords] {
+        self.levels.first().map_or(&[], |l| l.as_slice())
+    }
+
+    pub fn rating(&self, node: &Coords) -> u64 {
+        self.ratings.get(node).copied().unwrap_or(0)
+    }
 
-    while let Some(n) = queue.pop() {
-        let (point, path) = n;
-        let next = successors(map, &point)
+    pub fn total_rating(&self) -> u64 {
+        self.trailheads().iter().map(|h| self.rating(h)).sum()
+    }
+
+    fn next_steps(&self, node: &Coords) -> Vec<Coords> {
+        successors(self.map, node, self.config.step)
             .into_iter()
-            .map(|v| v.0)
+            .filter(|n| self.rating(n) > 0)
+            .collect()
+    }
+
+    fn reachable_bits(&self) -> HashMap<Coords, Vec<u64>> {
+        let Some(ends) = self.levels.last() else {
+            return HashMap::new();
+        };
+        let words = ends.len().div_ceil(64);
+
+        let mut reachable: HashMap<Coords, Vec<u64>> = HashMap::new();
+        for (k, level) in self.levels.iter().enumerate().rev() {
+            for c in level {
+                let mut bits = vec![0u64; words];
+                if k + 1 == self.levels.len() {
+                    let i = ends.binary_search(c).unwrap_or(0);
+                    bits[i / 64] |= 1 << (i % 64);
+                } else {
+                    for n in self.next_steps(c) {
+                        bits.iter_mut()
+                            .zip(&reachable[&n])
+                            .for_each(|(b, r)| *b |= r);
+                    }
+                }
+                reachable.insert(*c, bits);
+            }
+        }
+
+        reachable
+    }
+
+    pub fn scores(&self) -> HashMap<Coords, usize> {
+        let reachable = self.reachable_bits();
+
+        self.trailheads()
+            .iter()
+            .map(|h| {
+                let score = reachable[h].iter().map(|b| b.count_ones() as usize).sum();
+                (*h, score)
+            })
+            .collect()
+    }
+
+    pub fn reachable(&self) -> HashMap<Coords, Vec<Coords>> {
+        let reachable = self.reachable_bits();
+        let ends = self.levels.last().map_or(&[][..], |l| l.as_slice());
+
+        self.trailheads()
+            .iter()
+            .map(|h| {
+                let summits = ends
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| reachable[h][i / 64] & (1 << (i % 64)) != 0)
+                    .map(|(_, e)| *e)
+                    .collect();
+                (*h, summits)
+            })
+            .collect()
+    }
+
+    fn arrivals(&self) -> HashMap<Coords, u64> {
+        let mut arrivals = self
+            .trailheads()
+            .iter()
+            .map(|h| (*h, 1))
+            .collect::<HashMap<_, _>>();
+
+        for level in &self.levels {
+            for c in level {
+                let count = arrivals.get(c).copied().unwrap_or(0);
+                if count == 0 {
+                    continue;
+                }
+                for n in self.next_steps(c) {
+                    *arrivals.entry(n).or_default() += count;
+                }
+            }
+        }
+
+        arrivals
+    }
+
+    pub fn most_rated_summit(&self) -> Option<(Coords, u64)> {
+        let arrivals = self.arrivals();
+        self.levels
+            .last()?
+            .iter()
+            .map(|s| (*s, arrivals.get(s).copied().unwrap_or(0)))
+            .filter(|(_, n)| *n > 0)
+            .max_by_key(|(s, n)| (*n, std::cmp::Reverse(*s)))
+    }
+
+    fn trail_cells(&self, head: &Coords) -> HashSet<Coords> {
+        let mut cells = HashSet::new();
+        let mut queue = if self.rating(head) > 0 {
+            vec![*head]
+        } else {
+            vec![]
+        };
+
+        while let Some(c) = queue.pop() {
+            if cells.insert(c) {
+                queue.append(&mut self.next_steps(&c));
+            }
+        }
+
+        cells
+    }
+
+    pub fn overlaps(&self) -> Vec<((Coords, Coords), usize)> {
+        let cells = self
+            .trailheads()
+            .iter()
+            .map(|h| (*h, self.trail_cells(h)))
             .collect::<Vec<_>>();
 
-        let mut collect = next
+        cells
+            .iter()
+            .tuple_combinations()
+            .filter_map(|((a, ca), (b, cb))| {
+                let shared = ca.intersection(cb).count();
+                (shared > 0).then_some(((*a, *b), shared))
+            })
+            .collect()
+    }
+
+    pub fn heatmap(&self) -> HashMap<Coords, u64> {
+        self.arrivals()
             .into_iter()
-            .map(|p| {
-                let mut foo = path.clone();
-                foo.push(p);
-                (p, foo)
+            .map(|(c, n)| (c, n * self.rating(&c)))
+            .filter(|(_, n)| *n > 0)
+            .collect()
+    }
+
+    fn heatmap_rows(&self) -> Vec<Vec<u64>> {
+        let heatmap = self.heatmap();
+        let height = self.map.keys().map(|c| c.0 + 1).max().unwrap_or(0);
+        let width = self.map.keys().map(|c| c.1 + 1).max().unwrap_or(0);
+
+        (0..height)
+            .map(|i| {
+                (0..width)
+                    .map(|j| heatmap.get(&(i, j)).copied().unwrap_or(0))
+                    .collect()
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
 
-        let mut new_paths = collect.iter().map(|p| p.1.clone()).collect::<Vec<_>>();
-        all_paths.append(&mut new_paths);
+    pub fn heatmap_grid(&self) -> String {
+        let rows = self.heatmap_rows();
+        let width = rows
+            .iter()
+            .flatten()
+            .map(|n| n.to_string().len())
+            .max()
+            .unwrap_or(1);
 
-        queue.append(&mut collect);
+        rows.iter()
+            .map(|r| r.iter().map(|n| format!("{n:>width$}")).join(" "))
+            .join("\n")
     }
 
-    let number_unique_paths = all_paths
-        .iter()
-        .filter_map(|p| {
-            let f = p.first()?;
-            let l = p.last()?;
+    pub fn heatmap_json(&self) -> serde_json::Value {
+        let rows = self.heatmap_rows();
+        serde_json::json!({
+            "height": rows.len(),
+            "width": rows.first().map_or(0, |r| r.len()),
+            "cells": rows,
+        })
+    }
 
-            let fv = map.get(f)?;
-            let lv = map.get(l)?;
+    pub fn trails(&self, head: &Coords) -> Trails<'_, 'a> {
+        let start = if self.rating(head) > 0 && self.trailheads().contains(head) {
+            vec![*head]
+        } else {
+            vec![]
+        };
 
-            if *fv == '0' && *lv == '9' {
-                Some(1)
-            } else {
-                None
+        Trails {
+            trail_map: self,
+            stack: vec![start],
+            path: vec![],
+        }
+    }
+}
+
+pub struct Trails<'t, 'a> {
+    trail_map: &'t TrailMap<'a>,
+    stack: Vec<Vec<Coords>>,
+    path: Vec<Coords>,
+}
+
+impl Iterator for Trails<'_, '_> {
+    type Item = Vec<Coords>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.pop() {
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                }
+                Some(c) => {
+                    self.path.push(c);
+                    if self.path.len() == self.trail_map.levels.len() {
+                        let trail = self.path.clone();
+                        self.path.pop();
+                        return Some(trail);
+                    }
+
+                    let mut next = self.trail_map.next_steps(&c);
+                    next.reverse();
+                    self.stack.push(next);
+                }
             }
-        })
-        .sum();
+        }
+    }
+}
 
-    number_unique_paths
+#[aoc(day10, part1)]
+pub fn solve_part1(input: &Map) -> Result<u32> {
+    let trails = TrailMap::new(input, TrailConfig::default());
+    Ok(trails.scores().values().sum::<usize>() as u32)
 }
 
 #[aoc(day10, part2)]
 pub fn solve_part2(input: &Map) -> Result<usize> {
-    let paths = input
-        .iter()
-        .filter(|(_, v)| **v == '0')
-        .map(|(c, _)| c)
-        .map(|head| find_all_paths(input, head))
-        .collect::<Vec<_>>();
-
-    Ok(paths.iter().sum::<usize>())
+    let trails = TrailMap::new(input, TrailConfig::default());
+    Ok(trails.total_rating() as usize)
 }
 
 #[cfg(test)]
@@ -162,4 +413,90 @@ mod test {
         let data = input_generator(input2())?;
         Ok(assert_eq!(16, solve_part2(&data)?))
     }
+
+    #[test]
+    fn trails() -> Result<()> {
+        let data = input_generator(input1())?;
+        let trails = TrailMap::new(&data, TrailConfig::default());
+        let head = trails.trailheads()[0];
+
+        let all = trails.trails(&head).collect::<Vec<_>>();
+        assert_eq!(trails.rating(&head) as usize, all.len());
+        assert!(all.iter().all(|t| t.len() == 10 && t[0] == head));
+        assert_eq!(Some(&'9'), data.get(&all[0][9]));
+        Ok(assert_eq!(
+            trails.total_rating() as usize,
+            trails
+                .trailheads()
+                .iter()
+                .map(|h| trails.trails(h).count())
+                .sum::<usize>()
+        ))
+    }
+
+    #[test]
+    fn config() -> Result<()> {
+        let data = input_generator(input1())?;
+
+        let descending = TrailMap::new(
+            &data,
+            TrailConfig {
+                start: 9,
+                end: 0,
+                step: -1,
+            },
+        );
+        assert_eq!(81, descending.total_rating());
+
+        let even = TrailMap::new(
+            &data,
+            TrailConfig {
+                start: 0,
+                end: 8,
+                step: 2,
+            },
+        );
+        assert_eq!(0, even.total_rating());
+
+        let short = TrailMap::new(
+            &data,
+            TrailConfig {
+                start: 7,
+                end: 9,
+                step: 1,
+            },
+        );
+        Ok(assert!(short.total_rating() > 0))
+    }
+
+    #[test]
+    fn analytics() -> Result<()> {
+        let data = input_generator(input2())?;
+        let trails = TrailMap::new(&data, TrailConfig::default());
+
+        assert_eq!(HashMap::from([((0, 0), vec![(3, 0)])]), trails.reachable());
+        assert_eq!(Some(((3, 0), 16)), trails.most_rated_summit());
+        assert_eq!(Vec::<((Coords, Coords), usize)>::new(), trails.overlaps());
+        assert_eq!(
+            "16 12  8  4\n 4  8 12 16\n 4  8 12 16\n16 12  8  4",
+            trails.heatmap_grid()
+        );
+        assert_eq!(16, trails.heatmap_json()["cells"][3][0]);
+
+        let data = input_generator(input1())?;
+        let trails = TrailMap::new(&data, TrailConfig::default());
+        let reachable = trails.reachable();
+        let scores = trails.scores();
+        assert!(scores.iter().all(|(h, s)| reachable[h].len() == *s));
+        assert!(!trails.overlaps().is_empty());
+        Ok(assert_eq!(
+            trails.total_rating(),
+            trails
+                .heatmap()
+                .iter()
+                .filter(|(c, _)| data[c] == '0')
+                .map(|(_, n)| n)
+                .sum::<u64>()
+        ))
+    }
 }
@@ -1,7 +1,14 @@
-use anyhow::Result;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::{Context, Error, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use memoize::memoize;
-use rayon::prelude::*;
+use itertools::Itertools;
+use num_bigint::BigUint;
+
+use crate::utils::AocError::*;
 
 #[aoc_generator(day11)]
 pub fn input_generator(input: &str) -> Result<Vec<u128>> {
@@ -13,7 +20,7 @@ pub fn input_generator(input: &str) -> Result<Vec<u128>> {
 }
 
 fn ilen(v: u128) -> u32 {
-    v.ilog10() + 1
+    v.checked_ilog10().unwrap_or(0) + 1
 }
 
 fn split(v: u128) -> (u128, u128) {
@@ -25,53 +32,429 @@ fn split(v: u128) -> (u128, u128) {
     (left, right)
 }
 
-fn blink(stones: &[u128]) -> Vec<u128> {
-    stones
-        .iter()
-        .flat_map(|&v| match v {
-            0 => vec![1],
-            x if ilen(x) % 2 == 0 => {
-                let (left, right) = split(x);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    fn holds(&self, lhs: u128, rhs: u128) -> bool {
+        match self {
+            Cmp::Eq => lhs == rhs,
+            Cmp::Ne => lhs != rhs,
+            Cmp::Lt => lhs < rhs,
+            Cmp::Le => lhs <= rhs,
+            Cmp::Gt => lhs > rhs,
+            Cmp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl FromStr for Cmp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "<" => Cmp::Lt,
+            "<=" => Cmp::Le,
+            ">" => Cmp::Gt,
+            ">=" => Cmp::Ge,
+            _ => Err(GenericError).context("Unknown comparison")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    Value(Cmp, u128),
+    Digits(Cmp, u128),
+    ValueEven(bool),
+    DigitsEven(bool),
+}
+
+impl Condition {
+    fn holds(&self, v: u128) -> bool {
+        match self {
+            Condition::Always => true,
+            Condition::Value(cmp, n) => cmp.holds(v, *n),
+            Condition::Digits(cmp, n) => cmp.holds(ilen(v) as u128, *n),
+            Condition::ValueEven(even) => v.is_multiple_of(2) == *even,
+            Condition::DigitsEven(even) => ilen(v).is_multiple_of(2) == *even,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = s.split_whitespace().collect::<Vec<_>>();
+
+        Ok(match tokens.as_slice() {
+            ["always"] | ["*"] => Condition::Always,
+            ["value", "even"] => Condition::ValueEven(true),
+            ["value", "odd"] => Condition::ValueEven(false),
+            ["digits", "even"] => Condition::DigitsEven(true),
+            ["digits", "odd"] => Condition::DigitsEven(false),
+            ["value", cmp, n] => Condition::Value(cmp.parse()?, n.parse()?),
+            ["digits", cmp, n] => Condition::Digits(cmp.parse()?, n.parse()?),
+            _ => Err(GenericError).context(format!("Could not parse condition '{s}'"))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Replace(u128),
+    Split,
+    Multiply(u128),
+    Add(u128),
+}
+
+impl Action {
+    fn apply(&self, v: u128) -> Option<Vec<u128>> {
+        Some(match self {
+            Action::Replace(n) => vec![*n],
+            Action::Split => {
+                let (left, right) = split(v);
                 vec![left, right]
             }
-            x => vec![x * 2024],
+            Action::Multiply(n) => vec![v.checked_mul(*n)?],
+            Action::Add(n) => vec![v.checked_add(*n)?],
         })
-        .collect::<Vec<_>>()
+    }
 }
 
-#[aoc(day11, part1)]
-pub fn solve_part1(input: &[u128]) -> Result<usize> {
-    let mut stones = input.to_vec();
-    for _ in 0..25 {
-        stones = blink(&stones);
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = s.split_whitespace().collect::<Vec<_>>();
+
+        Ok(match tokens.as_slice() {
+            ["split"] => Action::Split,
+            ["replace", n] => Action::Replace(n.parse()?),
+            ["multiply", n] => Action::Multiply(n.parse()?),
+            ["add", n] => Action::Add(n.parse()?),
+            _ => Err(GenericError).context(format!("Could not parse action '{s}'"))?,
+        })
     }
-    Ok(stones.len())
 }
 
-#[memoize]
-fn blink_fast(stone: u128, lvl: u32, max: u32) -> u128 {
-    if lvl == max {
-        return 1;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    conditions: Vec<Condition>,
+    action: Action,
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (conditions, action) = s
+            .split_once("->")
+            .ok_or(GenericError)
+            .context("Rule is missing '->'")?;
+
+        Ok(Rule {
+            conditions: conditions
+                .split(" and ")
+                .map(|c| c.parse())
+                .collect::<Result<Vec<_>>>()?,
+            action: action.parse()?,
+        })
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl FromStr for RuleSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rules = s
+            .lines()
+            .map(|l| l.split('#').next().unwrap_or("").trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.parse())
+            .collect::<Result<Vec<_>>>()?;
 
-    match stone {
-        0 => blink_fast(1, lvl + 1, max),
-        x if ilen(x) % 2 == 0 => {
-            let (left, right) = split(x);
-            blink_fast(left, lvl + 1, max) + blink_fast(right, lvl + 1, max)
+        Ok(RuleSet { rules })
+    }
+}
+
+impl RuleSet {
+    pub fn standard() -> Self {
+        RuleSet {
+            rules: vec![
+                Rule {
+                    conditions: vec![Condition::Value(Cmp::Eq, 0)],
+                    action: Action::Replace(1),
+                },
+                Rule {
+                    conditions: vec![Condition::DigitsEven(true)],
+                    action: Action::Split,
+                },
+                Rule {
+                    conditions: vec![Condition::Always],
+                    action: Action::Multiply(2024),
+                },
+            ],
         }
-        x => blink_fast(x * 2024, lvl + 1, max),
+    }
+
+    pub fn apply(&self, v: u128) -> Option<Vec<u128>> {
+        match self
+            .rules
+            .iter()
+            .find(|r| r.conditions.iter().all(|c| c.holds(v)))
+        {
+            Some(rule) => rule.action.apply(v),
+            None => Some(vec![v]),
+        }
+    }
+
+    pub fn blink<C: StoneCount>(&self, stones: &HashMap<u128, C>) -> Result<HashMap<u128, C>> {
+        let mut next: HashMap<u128, C> = HashMap::new();
+        for (stone, count) in stones {
+            let children = self
+                .apply(*stone)
+                .ok_or(GenericError)
+                .context("Stone value overflow")?;
+            for child in children {
+                let entry = next.entry(child).or_default();
+                *entry = entry
+                    .checked_add(count)
+                    .ok_or(GenericError)
+                    .context("Stone count overflow")?;
+            }
+        }
+        Ok(next)
+    }
+
+    pub fn distribution(&self, stones: &[u128], blinks: usize) -> Result<HashMap<u128, u128>> {
+        let mut counts: HashMap<u128, u128> = HashMap::new();
+        for stone in stones {
+            *counts.entry(*stone).or_default() += 1;
+        }
+
+        for _ in 0..blinks {
+            counts = self.blink(&counts)?;
+        }
+
+        Ok(counts)
+    }
+
+    pub fn count(&self, stones: &[u128], blinks: usize) -> Result<u128> {
+        self.distribution(stones, blinks)?
+            .values()
+            .try_fold(0u128, |acc, c| acc.checked_add(*c))
+            .ok_or(GenericError)
+            .context("Stone count overflow")
+    }
+
+    pub fn count_with(
+        &self,
+        stones: &[u128],
+        blinks: usize,
+        counting: Counting,
+    ) -> Result<BigUint> {
+        let modulus = match counting {
+            Counting::Exact => None,
+            Counting::Modulo(0) => Err(GenericError).context("Modulus must not be zero")?,
+            Counting::Modulo(m) => Some(BigUint::from(m)),
+        };
+
+        let mut counts: HashMap<u128, BigUint> = HashMap::new();
+        for stone in stones {
+            *counts.entry(*stone).or_default() += 1u32;
+        }
+
+        for _ in 0..blinks {
+            counts = self.blink(&counts)?;
+            if let Some(m) = &modulus {
+                counts.values_mut().for_each(|c| *c %= m);
+            }
+        }
+
+        let total = counts.values().sum::<BigUint>();
+        Ok(match &modulus {
+            Some(m) => total % m,
+            None => total,
+        })
+    }
+
+    pub fn closure(&self, stones: &[u128], max_values: usize) -> Result<Option<Closure>> {
+        let mut values = stones.iter().copied().unique().collect::<Vec<_>>();
+        let mut seen = values.iter().copied().collect::<HashSet<_>>();
+        let mut frontier = values.clone();
+        let mut blinks = 0;
+
+        while !frontier.is_empty() {
+            let mut next = vec![];
+            for v in frontier {
+                let children = self
+                    .apply(v)
+                    .ok_or(GenericError)
+                    .context("Stone value overflow")?;
+                next.extend(children.into_iter().filter(|c| seen.insert(*c)));
+            }
+
+            if seen.len() > max_values {
+                return Ok(None);
+            }
+            if !next.is_empty() {
+                blinks += 1;
+            }
+            values.extend(&next);
+            frontier = next;
+        }
+
+        values.sort();
+        Ok(Some(Closure { values, blinks }))
+    }
+
+    pub fn growth(&self, stones: &[u128], max_values: usize) -> Result<Option<Growth>> {
+        let Some(closure) = self.closure(stones, max_values)? else {
+            return Ok(None);
+        };
+
+        let index = |v: &u128| closure.values.binary_search(v).unwrap_or(0);
+        let transitions = closure
+            .values
+            .iter()
+            .map(|v| {
+                let mut children = HashMap::new();
+                for child in self.apply(*v).unwrap_or_default() {
+                    *children.entry(index(&child)).or_default() += 1;
+                }
+                children.into_iter().sorted().collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut initial = vec![0.0; closure.values.len()];
+        for stone in stones {
+            initial[index(stone)] += 1.0;
+        }
+
+        let mut growth = Growth {
+            closure,
+            transitions,
+            initial,
+            eigenvalue: 0.0,
+        };
+        growth.eigenvalue = growth.dominant_eigenvalue();
+        Ok(Some(growth))
+    }
+}
+
+pub trait StoneCount: Clone + Default {
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl StoneCount for u128 {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u128::checked_add(*self, *other)
+    }
+}
+
+impl StoneCount for BigUint {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counting {
+    Exact,
+    Modulo(u128),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Closure {
+    pub values: Vec<u128>,
+    pub blinks: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Growth {
+    pub closure: Closure,
+    transitions: Vec<Vec<(usize, u128)>>,
+    initial: Vec<f64>,
+    pub eigenvalue: f64,
+}
+
+impl Growth {
+    const ITERATIONS: usize = 10_000;
+
+    fn step(&self, x: &[f64]) -> Vec<f64> {
+        let mut y = vec![0.0; x.len()];
+        for (i, children) in self.transitions.iter().enumerate() {
+            for (j, n) in children {
+                y[*j] += x[i] * *n as f64;
+            }
+        }
+        y
+    }
+
+    // Power iteration on M + I, which has the same dominant eigenvector as M but
+    // converges even if M is periodic.
+    fn dominant_eigenvalue(&self) -> f64 {
+        let mut x = self.initial.clone();
+        let mut lambda = 0.0;
+
+        for _ in 0..Self::ITERATIONS {
+            let y = self.step(&x);
+            let total = x.iter().sum::<f64>();
+            let shifted = y.iter().zip(&x).map(|(a, b)| a + b).collect::<Vec<_>>();
+            let next = shifted.iter().sum::<f64>() / total - 1.0;
+
+            x = shifted.into_iter().map(|v| v / total).collect();
+            if (next - lambda).abs() < 1e-15 {
+                return next;
+            }
+            lambda = next;
+        }
+
+        lambda
+    }
+
+    pub fn log10_count(&self, blinks: u128) -> f64 {
+        let mut x = self.initial.clone();
+        let mut log = x.iter().sum::<f64>().log10();
+        let simulated = blinks.min(Self::ITERATIONS as u128);
+
+        for _ in 0..simulated {
+            let total = x.iter().sum::<f64>();
+            let y = self.step(&x);
+            let next = y.iter().sum::<f64>();
+            log += (next / total).log10();
+            x = y.into_iter().map(|v| v / next).collect();
+        }
+
+        log + (blinks - simulated) as f64 * self.eigenvalue.log10()
     }
 }
 
+#[aoc(day11, part1)]
+pub fn solve_part1(input: &[u128]) -> Result<usize> {
+    Ok(RuleSet::standard().count(input, 25)? as usize)
+}
+
 #[aoc(day11, part2)]
 pub fn solve_part2(input: &[u128]) -> Result<u128> {
-    let sum = input
-        .par_iter()
-        .map(|s| blink_fast(*s, 0, 75))
-        .sum::<u128>();
-
-    Ok(sum)
+    RuleSet::standard().count(input, 75)
 }
 
 #[cfg(test)]
@@ -93,4 +476,111 @@ mod test {
         let data = input_generator(input())?;
         Ok(assert_eq!(65601038650482, solve_part2(&data)?))
     }
+
+    #[test]
+    fn dsl() -> Result<()> {
+        let rules = "# the puzzle rules
+value == 0 -> replace 1
+digits even -> split
+always -> multiply 2024"
+            .parse::<RuleSet>()?;
+        assert_eq!(RuleSet::standard(), rules);
+
+        let data = input_generator(input())?;
+        assert_eq!(22, rules.count(&data, 6)?);
+        assert_eq!(2705183445934430257146293156, rules.count(&data, 150)?);
+        assert!(rules.count(&data, 212).is_err());
+        Ok(assert!(rules.count(&data, 230).is_err()))
+    }
+
+    #[test]
+    fn custom_rules() -> Result<()> {
+        let rules = "value < 10 and value odd -> add 1
+value >= 100 and digits odd -> replace 0
+digits == 2 -> split
+* -> multiply 3"
+            .parse::<RuleSet>()?;
+
+        assert_eq!(Some(vec![4]), rules.apply(3));
+        assert_eq!(Some(vec![0]), rules.apply(123));
+        assert_eq!(Some(vec![4, 2]), rules.apply(42));
+        assert_eq!(Some(vec![12]), rules.apply(4));
+        assert!("value ~ 3 -> split".parse::<RuleSet>().is_err());
+        Ok(assert!("always -> explode".parse::<RuleSet>().is_err()))
+    }
+
+    #[test]
+    fn distribution() -> Result<()> {
+        let rules = RuleSet::standard();
+        assert_eq!(
+            HashMap::from([(20, 1), (24, 1)]),
+            rules.distribution(&[0], 3)?
+        );
+        Ok(assert_eq!(
+            HashMap::from([(2, 2), (0, 1), (4, 1)]),
+            rules.distribution(&[0], 4)?
+        ))
+    }
+
+    #[test]
+    fn growth() -> Result<()> {
+        let rules = RuleSet::standard();
+        let data = input_generator(input())?;
+
+        let closure = rules.closure(&data, 10_000)?.ok_or(GenericError)?;
+        assert!(closure.values.contains(&2024));
+        assert!(closure.values.iter().all(|v| rules
+            .apply(*v)
+            .unwrap_or_default()
+            .iter()
+            .all(|c| closure.values.contains(c))));
+        assert!(closure.blinks > 0);
+        assert_eq!(None, rules.closure(&data, 10)?);
+
+        let growth = rules.growth(&data, 10_000)?.ok_or(GenericError)?;
+        assert!(growth.eigenvalue > 1.0 && growth.eigenvalue < 2.0);
+        assert!((growth.log10_count(75) - 65601038650482_f64.log10()).abs() < 1e-9);
+
+        let far = growth.log10_count(1_000_000_000_000_000_000);
+        let expected = 1e18 * growth.eigenvalue.log10();
+        assert!((far - expected).abs() / expected < 1e-9);
+
+        let simulated = growth.log10_count(5000) - growth.log10_count(4000);
+        Ok(assert!(
+            (simulated - 1000.0 * growth.eigenvalue.log10()).abs() < 1e-6
+        ))
+    }
+
+    #[test]
+    fn big_counts() -> Result<()> {
+        let rules = RuleSet::standard();
+        let data = input_generator(input())?;
+
+        assert_eq!(
+            BigUint::from(65601038650482_u128),
+            rules.count_with(&data, 75, Counting::Exact)?
+        );
+        assert_eq!(
+            BigUint::from(rules.count(&data, 150)?),
+            rules.count_with(&data, 150, Counting::Exact)?
+        );
+
+        let exact = rules.count_with(&data, 2000, Counting::Exact)?;
+        let growth = rules.growth(&data, 10_000)?.ok_or(GenericError)?;
+        let digits = exact.to_string().len() as f64;
+        assert!((digits - growth.log10_count(2000).ceil()).abs() <= 1.0);
+
+        let modulus = 1_000_000_007;
+        assert_eq!(
+            &exact % modulus,
+            rules.count_with(&data, 2000, Counting::Modulo(modulus))?
+        );
+        assert_eq!(
+            &exact % u128::MAX,
+            rules.count_with(&data, 2000, Counting::Modulo(u128::MAX))?
+        );
+        Ok(assert!(rules
+            .count_with(&data, 1, Counting::Modulo(0))
+            .is_err()))
+    }
 }
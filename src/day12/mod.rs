@@ -1,7 +1,12 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Write,
+};
 
 use anyhow::Result;
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
 
 type Base = i32;
 type Coords = (Base, Base);
@@ -29,222 +34,401 @@ pub fn input_generator(input: &str) -> Result<PlotMap> {
     Ok(map)
 }
 
-fn has_label(map: &LabelMap, c: &Coords) -> bool {
-    map.get(c).is_some()
-}
+const DIRS: [Coords; 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
 
-fn next_plot_without_label(plots: &PlotMap, labels: &LabelMap) -> Option<(Coords, char)> {
-    plots
-        .iter()
-        .find(|p| !has_label(labels, p.0))
-        .map(|c| (*c.0, *c.1))
+#[derive(Debug, Clone)]
+struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
 }
 
-fn label_map(plots: &PlotMap) -> LabelMap {
-    let mut labels: LabelMap = HashMap::new();
-    let dirs = [(-1, 0), (0, -1), (1, 0), (0, 1)];
-    let mut current_label = 0;
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            rank: vec![0; len],
+        }
+    }
 
-    while let Some(p) = next_plot_without_label(plots, &labels) {
-        let mut queue = vec![p];
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
 
-        while let Some((c, t)) = queue.pop() {
-            labels.entry(c).or_insert(current_label);
-            let mut same_neighbors = dirs
-                .iter()
-                .map(|d| (d.0 + c.0, d.1 + c.1))
-                .filter_map(|c| Some((c, *plots.get(&c)?)))
-                .filter(|pt| pt.1 == t)
-                .filter(|pt| !has_label(&labels, &pt.0))
-                .collect::<Vec<_>>();
-            queue.append(&mut same_neighbors);
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
         }
 
-        current_label += 1;
+        match self.rank[a].cmp(&self.rank[b]) {
+            Ordering::Less => self.parent[a] = b,
+            Ordering::Greater => self.parent[b] = a,
+            Ordering::Equal => {
+                self.parent[b] = a;
+                self.rank[a] += 1;
+            }
+        }
     }
-
-    labels
 }
 
-fn count_other_neighbors(plots: &PlotMap, c: &Coords) -> usize {
-    let dirs = [(-1, 0), (0, -1), (1, 0), (0, 1)];
-
-    if let Some(t) = plots.get(c) {
-        let no_plots = dirs
-            .iter()
-            .map(|d| (d.0 + c.0, d.1 + c.1))
-            .filter(|c| plots.get(c).is_none())
-            .count();
+pub fn label_map(plots: &PlotMap) -> LabelMap {
+    let mut cells = plots.keys().copied().collect::<Vec<_>>();
+    cells.sort();
+    let index = cells
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (*c, i))
+        .collect::<HashMap<_, _>>();
 
-        let other_plots = dirs
-            .iter()
-            .map(|d| (d.0 + c.0, d.1 + c.1))
-            .filter_map(|c| Some((c, *plots.get(&c)?)))
-            .filter(|pt| pt.1 != *t)
-            .count();
-        no_plots + other_plots
-    } else {
-        0
+    let mut sets = UnionFind::new(cells.len());
+    for (i, c) in cells.iter().enumerate() {
+        for n in [(c.0 - 1, c.1), (c.0, c.1 - 1)] {
+            if let Some(j) = index.get(&n).filter(|_| plots.get(&n) == plots.get(c)) {
+                sets.union(i, *j);
+            }
+        }
     }
-}
 
-fn fencing_costs(label: u32, plots: &PlotMap, labels: &LabelMap) -> usize {
-    let points = labels
+    let mut roots = HashMap::new();
+    cells
         .iter()
-        .filter(|p| *p.1 == label)
-        .map(|p| *p.0)
-        .collect::<Vec<_>>();
-    let area = points.len();
-    let perimeter = points
-        .iter()
-        .map(|p| count_other_neighbors(plots, p))
-        .sum::<usize>();
+        .enumerate()
+        .map(|(i, c)| {
+            let next = roots.len() as u32;
+            (*c, *roots.entry(sets.find(i)).or_insert(next))
+        })
+        .collect()
+}
 
-    area * perimeter
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub label: u32,
+    pub plant: char,
+    cells: HashSet<Coords>,
 }
 
-#[aoc(day12, part1)]
-pub fn solve_part1(input: &PlotMap) -> Result<usize> {
-    let plots = input;
+pub fn regions(plots: &PlotMap) -> Vec<Region> {
     let labels = label_map(plots);
+    let mut regions = BTreeMap::new();
+    for (c, label) in &labels {
+        regions
+            .entry(*label)
+            .or_insert_with(|| Region {
+                label: *label,
+                plant: plots[c],
+                cells: HashSet::new(),
+            })
+            .cells
+            .insert(*c);
+    }
+    regions.into_values().collect()
+}
 
-    let label_list = labels.iter().map(|p| p.1).collect::<HashSet<_>>();
-    let costs = label_list
-        .iter()
-        .map(|l| fencing_costs(**l, plots, &labels))
-        .sum::<usize>();
+impl Region {
+    pub fn cells(&self) -> &HashSet<Coords> {
+        &self.cells
+    }
 
-    Ok(costs)
-}
+    pub fn area(&self) -> usize {
+        self.cells.len()
+    }
 
-fn new_perimeter(label: u32, labels: &LabelMap) -> usize {
-    let dirs = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+    pub fn perimeter(&self) -> usize {
+        self.boundary().len()
+    }
 
-    let polygon = labels
-        .iter()
-        .filter(|l| *l.1 == label)
-        .map(|l| *l.0)
-        .collect::<HashSet<_>>();
+    pub fn sides(&self) -> usize {
+        self.contours().iter().map(|c| c.len()).sum()
+    }
 
-    let sum = polygon
-        .iter()
-        .map(|c| {
-            let mut total = 0;
-            let outside = dirs
-                .iter()
-                .map(|d| (d.0 + c.0, d.1 + c.1))
-                .filter(|v| !polygon.contains(v))
-                .count();
-
-            // The center element is what we're looking at below's mini samples
-
-            // single cell -> four corners
-            // BBB
-            // BAB
-            // BBB
-            if outside == 4 {
-                total += 4;
-            }
+    pub fn bounding_box(&self) -> (Coords, Coords) {
+        let (rows, cols): (Vec<_>, Vec<_>) = self.cells.iter().copied().unzip();
+        let min = |v: &[Base]| v.iter().copied().min().unwrap_or(0);
+        let max = |v: &[Base]| v.iter().copied().max().unwrap_or(0);
+        ((min(&rows), min(&cols)), (max(&rows), max(&cols)))
+    }
 
-            // single row/column start/end -> 2 corners
-            // BBB
-            // BAB
-            // BAB
-            if outside == 3 {
-                total += 2;
+    pub fn holes(&self) -> Vec<HashSet<Coords>> {
+        let (min, max) = self.bounding_box();
+        let inside = |c: &Coords| c.0 >= min.0 && c.0 <= max.0 && c.1 >= min.1 && c.1 <= max.1;
+
+        let mut seen = HashSet::new();
+        let mut holes = vec![];
+        for row in min.0..=max.0 {
+            for col in min.1..=max.1 {
+                if self.cells.contains(&(row, col)) || seen.contains(&(row, col)) {
+                    continue;
+                }
+
+                let mut component = HashSet::new();
+                let mut enclosed = true;
+                let mut queue = vec![(row, col)];
+                seen.insert((row, col));
+                while let Some(c) = queue.pop() {
+                    component.insert(c);
+                    for n in DIRS.iter().map(|d| (c.0 + d.0, c.1 + d.1)) {
+                        if !inside(&n) {
+                            enclosed = false;
+                        } else if !self.cells.contains(&n) && seen.insert(n) {
+                            queue.push(n);
+                        }
+                    }
+                }
+
+                if enclosed {
+                    holes.push(component);
+                }
             }
+        }
+        holes
+    }
 
-            // outside == 2 could be inside a tube or a classic corner
-            // BAB
-            // BAB
-            // BAB
-            let is_inside_tube = (polygon.contains(&(c.0 - 1, c.1))
-                && polygon.contains(&(c.0 + 1, c.1)))
-                || (polygon.contains(&(c.0, c.1 - 1)) && polygon.contains(&(c.0, c.1 + 1)));
-
-            // classic corner
-            // AAB
-            // AAB
-            // BBB
-            if outside == 2 && !is_inside_tube {
-                total += 1;
-            }
+    // Edges run between cell corners, clockwise around the region and counterclockwise
+    // around holes, so the region is always on the right.
+    fn boundary(&self) -> Vec<(Coords, Coords)> {
+        self.cells
+            .iter()
+            .flat_map(|c| {
+                let corners = [
+                    (c.0, c.1),
+                    (c.0, c.1 + 1),
+                    (c.0 + 1, c.1 + 1),
+                    (c.0 + 1, c.1),
+                ];
+                DIRS.iter()
+                    .enumerate()
+                    .filter(|(_, d)| !self.cells.contains(&(c.0 + d.0, c.1 + d.1)))
+                    .map(move |(i, _)| (corners[i], corners[(i + 1) % 4]))
+            })
+            .collect()
+    }
 
+    pub fn contours(&self) -> Vec<Vec<Coords>> {
+        let mut unused = self.boundary().into_iter().collect::<BTreeSet<_>>();
+        let mut outgoing: HashMap<Coords, Vec<Coords>> = HashMap::new();
+        for (from, to) in &unused {
+            outgoing.entry(*from).or_default().push(*to);
+        }
 
-            // BAA
-            // AAA
-            // AAA
-            if !polygon.contains(&(c.0 - 1, c.1 - 1))
-                && polygon.contains(&(c.0 - 1, c.1))
-                && polygon.contains(&(c.0, c.1 - 1))
-            {
-                total += 1;
+        let dir = |(from, to): (Coords, Coords)| (to.0 - from.0, to.1 - from.1);
+        let mut contours = vec![];
+        while let Some(first) = unused.pop_first() {
+            let mut edges = vec![first];
+            loop {
+                let (from, at) = edges[edges.len() - 1];
+                let d = dir((from, at));
+                // Two edges only leave the same corner where the region touches itself
+                // diagonally; turning left keeps the cells on the other diagonal apart.
+                let next = outgoing[&at]
+                    .iter()
+                    .map(|to| (at, *to))
+                    .max_by_key(|e| {
+                        let n = dir(*e);
+                        d.0 * n.1 - d.1 * n.0
+                    })
+                    .unwrap_or(first);
+                if next == first {
+                    break;
+                }
+                unused.remove(&next);
+                edges.push(next);
             }
 
-            // AAB
-            // AAA
-            // AAA
-            if !polygon.contains(&(c.0 - 1, c.1 + 1))
-                && polygon.contains(&(c.0 - 1, c.1))
-                && polygon.contains(&(c.0, c.1 + 1))
-            {
-                total += 1;
-            }
+            let polygon = edges
+                .iter()
+                .zip(edges.iter().cycle().skip(edges.len() - 1))
+                .filter(|(e, prev)| dir(**e) != dir(**prev))
+                .map(|(e, _)| e.0)
+                .collect::<Vec<_>>();
+            contours.push(polygon);
+        }
 
-            // AAA
-            // AAA
-            // AAB
-            if !polygon.contains(&(c.0 + 1, c.1 + 1))
-                && polygon.contains(&(c.0 + 1, c.1))
-                && polygon.contains(&(c.0, c.1 + 1))
-            {
-                total += 1;
-            }
+        contours.sort_by_key(|p| signed_area(p) < 0);
+        contours
+    }
+}
 
-            // AAA
-            // AAA
-            // BAA
-            if !polygon.contains(&(c.0 + 1, c.1 - 1))
-                && polygon.contains(&(c.0 + 1, c.1))
-                && polygon.contains(&(c.0, c.1 - 1))
-            {
-                total += 1;
-            }
+fn signed_area(polygon: &[Coords]) -> i64 {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.1 as i64 * b.0 as i64 - b.1 as i64 * a.0 as i64)
+        .sum()
+}
 
-            total
+fn plant_colour(plant: char) -> String {
+    format!("hsl({}, 60%, 70%)", plant as u32 * 137 % 360)
+}
+
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '&' => "&amp;".to_string(),
+            '"' => "&quot;".to_string(),
+            _ => c.to_string(),
         })
-        .sum::<usize>();
+        .collect()
+}
 
-    sum
+pub fn write_svg<W: Write>(plots: &PlotMap, scale: Base, out: &mut W) -> Result<()> {
+    let rows = plots.keys().map(|c| c.0 + 1).max().unwrap_or(0);
+    let cols = plots.keys().map(|c| c.1 + 1).max().unwrap_or(0);
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+        cols * scale,
+        rows * scale,
+        cols * scale,
+        rows * scale
+    )?;
+
+    for region in regions(plots) {
+        let path = region
+            .contours()
+            .iter()
+            .map(|polygon| {
+                let points = polygon
+                    .iter()
+                    .map(|v| format!("{} {}", v.1 * scale, v.0 * scale))
+                    .join(" L ");
+                format!("M {points} Z")
+            })
+            .join(" ");
+
+        let (min, max) = region.bounding_box();
+        let centre = (min.0 + max.0, min.1 + max.1);
+        let label = region
+            .cells()
+            .iter()
+            .min_by_key(|c| {
+                (
+                    (2 * c.0 - centre.0).pow(2) + (2 * c.1 - centre.1).pow(2),
+                    **c,
+                )
+            })
+            .copied()
+            .unwrap_or(min);
+
+        let (area, perimeter, sides) = (region.area(), region.perimeter(), region.sides());
+        writeln!(
+            out,
+            "  <g><title>{}: area {area}, perimeter {perimeter}, sides {sides}</title>",
+            escape(&region.plant.to_string())
+        )?;
+        writeln!(
+            out,
+            "    <path d=\"{path}\" fill=\"{}\" fill-rule=\"evenodd\" stroke=\"black\"/>",
+            plant_colour(region.plant)
+        )?;
+        writeln!(
+            out,
+            "    <text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\">A{area} P{perimeter} S{sides}</text></g>",
+            label.1 * scale + scale / 2,
+            label.0 * scale + scale / 2,
+            (scale / 4).max(1)
+        )?;
+    }
+
+    writeln!(out, "</svg>")?;
+    Ok(())
 }
 
-fn fencing_costs_with_discount(label: u32, labels: &LabelMap) -> usize {
-    let points = labels
+#[aoc(day12, part1)]
+pub fn solve_part1(input: &PlotMap) -> Result<usize> {
+    Ok(regions(input)
         .iter()
-        .filter(|p| *p.1 == label)
-        .map(|p| *p.0)
-        .collect::<Vec<_>>();
-    let area = points.len();
-    let perimeter = new_perimeter(label, labels);
-
-    area * perimeter
+        .map(|r| r.area() * r.perimeter())
+        .sum())
 }
 
 #[aoc(day12, part2)]
 pub fn solve_part2(input: &PlotMap) -> Result<usize> {
-    let plots = input;
-    let labels = label_map(plots);
-
-    let label_list = labels.iter().map(|p| p.1).collect::<HashSet<_>>();
-    let costs = label_list
-        .iter()
-        .map(|l| fencing_costs_with_discount(**l, &labels))
-        .sum::<usize>();
-
-    Ok(costs)
+    Ok(regions(input).iter().map(|r| r.area() * r.sides()).sum())
 }
 
 #[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
+
+    fn input() -> &'static str {
+        "RRRRIICCFF
+RRRRIICCCF
+VVRRRCCFFF
+VVRCCCJFFF
+VVVVCJJCFE
+VVIVCCJJEE
+VVIIICJJEE
+MIIIIIJJEE
+MIIISIJEEE
+MMMISSJEEE"
+    }
+
+    #[test]
+    fn part1() -> Result<()> {
+        let data = input_generator(input())?;
+        Ok(assert_eq!(1930, solve_part1(&data)?))
+    }
+
+    #[test]
+    fn part2() -> Result<()> {
+        let data = input_generator(input())?;
+        assert_eq!(1206, solve_part2(&data)?);
+
+        let data = input_generator("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA")?;
+        Ok(assert_eq!(368, solve_part2(&data)?))
+    }
+
+    #[test]
+    fn region_geometry() -> Result<()> {
+        let data = input_generator("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO")?;
+        let found = regions(&data);
+        assert_eq!(5, found.len());
+
+        let outer = &found[0];
+        assert_eq!('O', outer.plant);
+        assert_eq!(21, outer.area());
+        assert_eq!(36, outer.perimeter());
+        assert_eq!(20, outer.sides());
+        assert_eq!(((0, 0), (4, 4)), outer.bounding_box());
+        assert_eq!(4, outer.holes().len());
+        assert_eq!(vec![(0, 0), (0, 5), (5, 5), (5, 0)], outer.contours()[0]);
+
+        let data = input_generator("AAA\nABA\nAAB")?;
+        let found = regions(&data);
+        assert_eq!(3, found.len());
+        assert_eq!(vec![HashSet::from([(1, 1)])], found[0].holes());
+        assert_eq!(16, found[0].perimeter());
+        Ok(assert_eq!(
+            vec![
+                vec![(0, 0), (0, 3), (2, 3), (2, 2), (3, 2), (3, 0)],
+                vec![(1, 1), (2, 1), (2, 2), (1, 2)]
+            ],
+            found[0].contours()
+        ))
+    }
+
+    #[test]
+    fn svg() -> Result<()> {
+        let data = input_generator("AAA\nABA\nAAB")?;
+        let mut out = vec![];
+        write_svg(&data, 10, &mut out)?;
+        let svg = String::from_utf8(out)?;
+
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"30\" height=\"30\"")
+        );
+        assert!(svg.contains(
+            "d=\"M 0 0 L 30 0 L 30 20 L 20 20 L 20 30 L 0 30 Z M 10 10 L 10 20 L 20 20 L 20 10 Z\""
+        ));
+        assert!(svg.contains("<title>A: area 7, perimeter 16, sides 10</title>"));
+        assert!(svg.contains(">A1 P4 S4</text>"));
+        assert_eq!(3, svg.matches("<path").count());
+        Ok(assert!(svg.trim_end().ends_with("</svg>")))
+    }
 }
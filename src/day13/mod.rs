@@ -1,13 +1,67 @@
-use std::str::FromStr;
+use std::{cmp::Ordering, str::FromStr};
 
 use anyhow::{Context, Error, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use num_bigint::BigInt;
+use num_rational::BigRational;
 use rayon::prelude::*;
 
 use crate::utils::AocError::*;
 
 type Coords = (i128, i128);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolverConfig {
+    pub costs: [i128; 2],
+    pub limits: [Option<i128>; 2],
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            costs: [3, 1],
+            limits: [None, None],
+        }
+    }
+}
+
+impl SolverConfig {
+    pub fn limited(presses: i128) -> Self {
+        Self {
+            limits: [Some(presses); 2],
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub presses: [i128; 2],
+    pub tokens: i128,
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a.abs(), a.signum(), 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - a / b * y)
+    }
+}
+
+fn div_floor(n: i128, d: i128) -> i128 {
+    let q = n / d;
+    if n % d != 0 && (n < 0) != (d < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn div_ceil(n: i128, d: i128) -> i128 {
+    -div_floor(-n, d)
+}
+
 #[derive(Debug, Clone)]
 pub struct Machine {
     a: Coords,
@@ -20,24 +74,99 @@ impl Machine {
         Self { a, b, prize }
     }
 
-    pub fn tokens(&self) -> i128 {
-        let a = self.a;
-        let b = self.b;
-        let p = self.prize;
-
+    pub fn solve(&self, config: &SolverConfig) -> Option<Solution> {
+        let (a, b, p) = (self.a, self.b, self.prize);
         let disc = a.0 * b.1 - b.0 * a.1;
 
-        let fa = p.0 * b.1 - p.1 * b.0;
-        let fb = p.1 * a.0 - p.0 * a.1;
+        let presses = if disc != 0 {
+            let fa = p.0 * b.1 - p.1 * b.0;
+            let fb = p.1 * a.0 - p.0 * a.1;
+            if fa % disc != 0 || fb % disc != 0 {
+                return None;
+            }
+            [fa / disc, fb / disc]
+        } else {
+            self.solve_collinear(config)?
+        };
+
+        let within = |i: usize| presses[i] >= 0 && config.limits[i].is_none_or(|l| presses[i] <= l);
+        if !within(0) || !within(1) {
+            return None;
+        }
+
+        Some(Solution {
+            presses,
+            tokens: config.costs[0] * presses[0] + config.costs[1] * presses[1],
+        })
+    }
+
+    // With collinear buttons every press moves the claw along the same line, so the
+    // prize has to lie on it and one coordinate decides the whole equation.
+    fn solve_collinear(&self, config: &SolverConfig) -> Option<[i128; 2]> {
+        let (a, b, p) = (self.a, self.b, self.prize);
+        let Some(d) = [a, b].into_iter().find(|v| *v != (0, 0)) else {
+            return (p == (0, 0)).then_some([0, 0]);
+        };
+        if p.0 * d.1 - p.1 * d.0 != 0 {
+            return None;
+        }
+
+        let (x, y, c) = if d.0 != 0 {
+            (a.0, b.0, p.0)
+        } else {
+            (a.1, b.1, p.1)
+        };
+        let (g, s, t) = extended_gcd(x, y);
+        if c % g != 0 {
+            return None;
+        }
+
+        // All solutions are (s, t) * c / g + k * (y, -x) / g.
+        let base = [s * (c / g), t * (c / g)];
+        let step = [y / g, -x / g];
 
-        if fa % disc != 0 || fb % disc != 0 {
-            return 0;
+        let mut lo = None;
+        let mut hi: Option<i128> = None;
+        for i in 0..2 {
+            let limit = config.limits[i];
+            if step[i] == 0 {
+                if base[i] < 0 || limit.is_some_and(|l| base[i] > l) {
+                    return None;
+                }
+                continue;
+            }
+
+            let (lower, upper) = if step[i] > 0 {
+                (
+                    Some(div_ceil(-base[i], step[i])),
+                    limit.map(|l| div_floor(l - base[i], step[i])),
+                )
+            } else {
+                (
+                    limit.map(|l| div_ceil(l - base[i], step[i])),
+                    Some(div_floor(-base[i], step[i])),
+                )
+            };
+            lo = lo.max(lower);
+            hi = match (hi, upper) {
+                (Some(h), Some(u)) => Some(h.min(u)),
+                (h, u) => h.or(u),
+            };
         }
 
-        let ta = fa / disc;
-        let tb = fb / disc;
+        if let (Some(l), Some(h)) = (lo, hi) {
+            if l > h {
+                return None;
+            }
+        }
 
-        3 * ta + tb
+        let slope = config.costs[0] * step[0] + config.costs[1] * step[1];
+        let k = match slope.cmp(&0) {
+            Ordering::Less => hi?,
+            Ordering::Greater => lo?,
+            Ordering::Equal => lo.or(hi)?,
+        };
+        Some([base[0] + k * step[0], base[1] + k * step[1]])
     }
 }
 
@@ -85,9 +214,398 @@ pub fn input_generator(input: &str) -> Result<Vec<Machine>> {
         .collect::<Result<Vec<_>>>()
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Button {
+    pub name: String,
+    pub delta: Vec<i128>,
+    pub cost: i128,
+    pub limit: Option<i128>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClawMachine {
+    pub axes: Vec<String>,
+    pub buttons: Vec<Button>,
+    pub prize: Vec<i128>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub presses: Vec<i128>,
+    pub tokens: i128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Lp {
+    Infeasible,
+    Unbounded,
+    Optimal(Vec<BigRational>),
+}
+
+const MAX_NODES: usize = 100_000;
+
+fn rational(n: i128) -> BigRational {
+    BigRational::from_integer(BigInt::from(n))
+}
+
+fn pivot(tableau: &mut [Vec<BigRational>], basis: &mut [usize], row: usize, col: usize) {
+    let p = tableau[row][col].clone();
+    tableau[row].iter_mut().for_each(|v| *v /= &p);
+
+    let pivot_row = tableau[row].clone();
+    for (i, r) in tableau.iter_mut().enumerate() {
+        let f = r[col].clone();
+        if i == row || f == rational(0) {
+            continue;
+        }
+        r.iter_mut().zip(&pivot_row).for_each(|(v, p)| *v -= &f * p);
+    }
+    basis[row] = col;
+}
+
+// Bland's rule: the lowest eligible column enters and ties in the ratio test go to
+// the lowest basic column, which rules out cycling.
+fn optimise(
+    tableau: &mut [Vec<BigRational>],
+    basis: &mut [usize],
+    costs: &[BigRational],
+    columns: usize,
+) -> bool {
+    let zero = rational(0);
+    loop {
+        let entering = (0..columns).find(|j| {
+            let reduced = tableau
+                .iter()
+                .zip(basis.iter())
+                .fold(costs[*j].clone(), |acc, (r, b)| acc - &costs[*b] * &r[*j]);
+            reduced < zero
+        });
+        let Some(col) = entering else {
+            return true;
+        };
+
+        let rhs = tableau[0].len() - 1;
+        let leaving = (0..tableau.len())
+            .filter(|i| tableau[*i][col] > zero)
+            .min_by(|a, b| {
+                let ra = &tableau[*a][rhs] / &tableau[*a][col];
+                let rb = &tableau[*b][rhs] / &tableau[*b][col];
+                ra.cmp(&rb).then(basis[*a].cmp(&basis[*b]))
+            });
+        let Some(row) = leaving else {
+            return false;
+        };
+        pivot(tableau, basis, row, col);
+    }
+}
+
+// Minimises costs * x subject to rows * x = rhs (the last entry of every row) and x >= 0.
+fn simplex(rows: Vec<Vec<BigRational>>, costs: &[BigRational]) -> Lp {
+    let zero = rational(0);
+    let (m, n) = (rows.len(), costs.len());
+
+    let mut tableau = rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut row)| {
+            let rhs = row.pop().unwrap_or_else(|| rational(0));
+            if rhs < zero {
+                row.iter_mut().for_each(|v| *v = -v.clone());
+            }
+            row.extend((0..m).map(|j| rational((i == j) as i128)));
+            row.push(if rhs < zero { -rhs } else { rhs });
+            row
+        })
+        .collect::<Vec<_>>();
+    let mut basis = (n..n + m).collect::<Vec<_>>();
+
+    let artificial = (0..n + m)
+        .map(|j| rational((j >= n) as i128))
+        .collect::<Vec<_>>();
+    optimise(&mut tableau, &mut basis, &artificial, n + m);
+    let infeasibility = tableau
+        .iter()
+        .zip(&basis)
+        .filter(|(_, b)| **b >= n)
+        .fold(rational(0), |acc, (r, _)| acc + &r[n + m]);
+    if infeasibility > zero {
+        return Lp::Infeasible;
+    }
+
+    let mut i = 0;
+    while i < tableau.len() {
+        if basis[i] >= n {
+            if let Some(j) = (0..n).find(|j| tableau[i][*j] != zero) {
+                pivot(&mut tableau, &mut basis, i, j);
+            } else {
+                tableau.remove(i);
+                basis.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    let costs = costs
+        .iter()
+        .cloned()
+        .chain((0..m).map(|_| rational(0)))
+        .collect::<Vec<_>>();
+    if !optimise(&mut tableau, &mut basis, &costs, n) {
+        return Lp::Unbounded;
+    }
+
+    let mut values = vec![rational(0); n];
+    for (row, b) in tableau.iter().zip(&basis) {
+        values[*b] = row[n + m].clone();
+    }
+    Lp::Optimal(values)
+}
+
+impl ClawMachine {
+    pub fn from_machine(machine: &Machine, config: &SolverConfig) -> Self {
+        let button = |name: &str, v: Coords, i: usize| Button {
+            name: name.to_string(),
+            delta: vec![v.0, v.1],
+            cost: config.costs[i],
+            limit: config.limits[i],
+        };
+
+        Self {
+            axes: vec!["X".to_string(), "Y".to_string()],
+            buttons: vec![button("A", machine.a, 0), button("B", machine.b, 1)],
+            prize: vec![machine.prize.0, machine.prize.1],
+        }
+    }
+
+    fn relaxation(&self, lo: &[i128], hi: &[Option<i128>]) -> Lp {
+        let n = self.buttons.len();
+        let bounded = (0..n).filter(|i| hi[*i].is_some()).collect::<Vec<_>>();
+
+        let mut rows = self
+            .prize
+            .iter()
+            .enumerate()
+            .map(|(d, p)| {
+                let shifted = self
+                    .buttons
+                    .iter()
+                    .zip(lo)
+                    .fold(*p, |acc, (b, l)| acc - b.delta[d] * l);
+                self.buttons
+                    .iter()
+                    .map(|b| rational(b.delta[d]))
+                    .chain(bounded.iter().map(|_| rational(0)))
+                    .chain([rational(shifted)])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for (k, i) in bounded.iter().enumerate() {
+            let mut row = vec![rational(0); n + bounded.len() + 1];
+            row[*i] = rational(1);
+            row[n + k] = rational(1);
+            row[n + bounded.len()] = rational(hi[*i].unwrap_or(0) - lo[*i]);
+            rows.push(row);
+        }
+
+        let costs = self
+            .buttons
+            .iter()
+            .map(|b| rational(b.cost))
+            .chain(bounded.iter().map(|_| rational(0)))
+            .collect::<Vec<_>>();
+
+        match simplex(rows, &costs) {
+            Lp::Optimal(values) => Lp::Optimal(
+                values
+                    .into_iter()
+                    .zip(lo)
+                    .map(|(v, l)| v + rational(*l))
+                    .collect(),
+            ),
+            lp => lp,
+        }
+    }
+
+    pub fn solve(&self) -> Result<Option<Plan>> {
+        let feasible = self.prize.iter().enumerate().all(|(d, p)| {
+            let g = self
+                .buttons
+                .iter()
+                .fold(0, |g, b| extended_gcd(g, b.delta[d]).0);
+            if g == 0 {
+                *p == 0
+            } else {
+                p % g == 0
+            }
+        });
+        if !feasible {
+            return Ok(None);
+        }
+
+        let n = self.buttons.len();
+        let mut best: Option<Plan> = None;
+        let mut stack = vec![(
+            vec![0; n],
+            self.buttons.iter().map(|b| b.limit).collect::<Vec<_>>(),
+        )];
+        let mut nodes = 0;
+
+        while let Some((lo, hi)) = stack.pop() {
+            nodes += 1;
+            if nodes > MAX_NODES {
+                Err(GenericError).context("Search space too large")?;
+            }
+            if lo.iter().zip(&hi).any(|(l, h)| h.is_some_and(|h| h < *l)) {
+                continue;
+            }
+
+            let values = match self.relaxation(&lo, &hi) {
+                Lp::Infeasible => continue,
+                Lp::Unbounded => Err(GenericError).context("Token cost is unbounded")?,
+                Lp::Optimal(values) => values,
+            };
+
+            let cost = self
+                .buttons
+                .iter()
+                .zip(&values)
+                .fold(rational(0), |acc, (b, v)| acc + rational(b.cost) * v);
+            if best
+                .as_ref()
+                .is_some_and(|b| cost.ceil() >= rational(b.tokens))
+            {
+                continue;
+            }
+
+            match values.iter().position(|v| !v.is_integer()) {
+                Some(i) => {
+                    let floor = i128::try_from(values[i].floor().to_integer())?;
+                    let mut up = lo.clone();
+                    up[i] = floor + 1;
+                    stack.push((up, hi.clone()));
+
+                    let mut down = hi;
+                    down[i] = Some(floor);
+                    stack.push((lo, down));
+                }
+                None => {
+                    let presses = values
+                        .iter()
+                        .map(|v| Ok(i128::try_from(v.to_integer())?))
+                        .collect::<Result<Vec<_>>>()?;
+                    let tokens = i128::try_from(cost.to_integer())?;
+                    best = Some(Plan { presses, tokens });
+                }
+            }
+        }
+
+        Ok(best)
+    }
+}
+
+impl FromStr for ClawMachine {
+    type Err = Error;
+
+    // Buttons may carry a trailing "(cost 2, limit 100)". Without a cost the puzzle
+    // pricing applies: A costs 3 tokens, every other button 1.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut axes: Vec<String> = vec![];
+        let mut axis = |name: &str| {
+            axes.iter().position(|a| a == name).unwrap_or_else(|| {
+                axes.push(name.to_string());
+                axes.len() - 1
+            })
+        };
+        let mut components = |list: &str, separator: char| {
+            list.split(',')
+                .map(|c| {
+                    let c = c.trim();
+                    let split = c
+                        .find(|ch: char| !ch.is_alphabetic())
+                        .ok_or(GenericError)
+                        .context(format!("Could not parse component '{c}'"))?;
+                    let value = c[split..].trim_start_matches(separator).parse::<i128>()?;
+                    Ok((axis(&c[..split]), value))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+
+        let mut buttons = vec![];
+        let mut prize = None;
+        for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (head, rest) = line
+                .split_once(':')
+                .ok_or(GenericError)
+                .context(format!("Could not parse line '{line}'"))?;
+
+            if head == "Prize" {
+                prize = Some(components(rest, '=')?);
+                continue;
+            }
+
+            let name = head
+                .strip_prefix("Button ")
+                .ok_or(GenericError)
+                .context(format!("Unknown line '{line}'"))?
+                .to_string();
+            let (moves, options) = match rest.split_once('(') {
+                Some((m, o)) => (m, o.trim_end_matches(')')),
+                None => (rest, ""),
+            };
+
+            let mut cost = if name == "A" { 3 } else { 1 };
+            let mut limit = None;
+            for option in options.split(',').filter(|o| !o.trim().is_empty()) {
+                match option.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["cost", v] => cost = v.parse()?,
+                    ["limit", v] => limit = Some(v.parse()?),
+                    _ => Err(GenericError).context(format!("Unknown option '{option}'"))?,
+                }
+            }
+            buttons.push((name, components(moves, '+')?, cost, limit));
+        }
+
+        let prize = prize.ok_or(GenericError).context("Machine has no prize")?;
+        let dims = axes.len();
+        let vector = |components: Vec<(usize, i128)>| {
+            let mut v = vec![0; dims];
+            components.into_iter().for_each(|(d, n)| v[d] += n);
+            v
+        };
+
+        Ok(ClawMachine {
+            buttons: buttons
+                .into_iter()
+                .map(|(name, delta, cost, limit)| Button {
+                    name,
+                    delta: vector(delta),
+                    cost,
+                    limit,
+                })
+                .collect(),
+            prize: vector(prize),
+            axes,
+        })
+    }
+}
+
+pub fn extended_input_generator(input: &str) -> Result<Vec<ClawMachine>> {
+    input
+        .split("\n\n")
+        .map(ClawMachine::from_str)
+        .collect::<Result<Vec<_>>>()
+}
+
 #[aoc(day13, part1)]
 pub fn solve_part1(input: &[Machine]) -> Result<i128> {
-    Ok(input.par_iter().map(|m| m.tokens()).sum())
+    let config = SolverConfig::limited(100);
+    Ok(input
+        .par_iter()
+        .filter_map(|m| m.solve(&config))
+        .map(|s| s.tokens)
+        .sum())
 }
 
 #[aoc(day13, part2)]
@@ -101,7 +619,8 @@ pub fn solve_part2(input: &[Machine]) -> Result<i128> {
                 (m.prize.0 + 10000000000000, m.prize.1 + 10000000000000),
             )
         })
-        .map(|m| m.tokens())
+        .filter_map(|m| m.solve(&SolverConfig::default()))
+        .map(|s| s.tokens)
         .sum::<i128>())
 }
 
@@ -138,4 +657,125 @@ Prize: X=18641, Y=10279"
         let data = input_generator(input())?;
         Ok(assert_eq!(875318608908, solve_part2(&data)?))
     }
+
+    #[test]
+    fn presses() -> Result<()> {
+        let data = input_generator(input())?;
+        let config = SolverConfig::default();
+        assert_eq!(
+            Some(Solution {
+                presses: [80, 40],
+                tokens: 280
+            }),
+            data[0].solve(&config)
+        );
+        assert_eq!(None, data[1].solve(&config));
+        assert_eq!(None, Machine::new((1, 0), (0, 1), (-1, 2)).solve(&config));
+        Ok(assert_eq!(None, data[0].solve(&SolverConfig::limited(50))))
+    }
+
+    #[test]
+    fn collinear_buttons() {
+        let machine = Machine::new((1, 1), (3, 3), (10, 10));
+        let solve = |costs, limits| {
+            machine
+                .solve(&SolverConfig { costs, limits })
+                .map(|s| s.presses)
+        };
+
+        assert_eq!(Some([1, 3]), solve([3, 1], [None, None]));
+        assert_eq!(Some([10, 0]), solve([1, 5], [None, None]));
+        assert_eq!(Some([4, 2]), solve([1, 5], [Some(5), None]));
+        assert_eq!(None, solve([1, 5], [Some(5), Some(1)]));
+        assert_eq!(
+            None,
+            Machine::new((1, 1), (3, 3), (10, 11)).solve(&SolverConfig::default())
+        );
+        assert_eq!(
+            Some([2, 1]),
+            Machine::new((2, 2), (-3, -3), (1, 1))
+                .solve(&SolverConfig::default())
+                .map(|s| s.presses)
+        );
+        assert_eq!(
+            Some([0, 0]),
+            Machine::new((0, 0), (0, 0), (0, 0))
+                .solve(&SolverConfig::default())
+                .map(|s| s.presses)
+        );
+        assert_eq!(
+            Some([0, 5]),
+            Machine::new((0, 0), (0, 2), (0, 10))
+                .solve(&SolverConfig::default())
+                .map(|s| s.presses)
+        );
+    }
+
+    #[test]
+    fn extended_machines() -> Result<()> {
+        let machines = extended_input_generator(input())?;
+        let tokens = machines
+            .iter()
+            .map(|m| Ok(m.solve()?.map_or(0, |p| p.tokens)))
+            .sum::<Result<i128>>()?;
+        assert_eq!(480, tokens);
+
+        let far = input_generator(input())?
+            .iter()
+            .map(|m| {
+                Machine::new(
+                    m.a,
+                    m.b,
+                    (m.prize.0 + 10000000000000, m.prize.1 + 10000000000000),
+                )
+            })
+            .map(|m| {
+                Ok(ClawMachine::from_machine(&m, &SolverConfig::default())
+                    .solve()?
+                    .map_or(0, |p| p.tokens))
+            })
+            .sum::<Result<i128>>()?;
+        Ok(assert_eq!(875318608908, far))
+    }
+
+    #[test]
+    fn many_buttons() -> Result<()> {
+        let machine = ClawMachine::from_str(
+            "Button A: X+1 (cost 1)
+Button B: Y+1
+Button C: X+1, Y+1 (cost 1, limit 4)
+Prize: X=5, Y=7",
+        )?;
+        assert_eq!(vec!["X", "Y"], machine.axes);
+        assert_eq!(
+            Some(Plan {
+                presses: vec![1, 3, 4],
+                tokens: 8
+            }),
+            machine.solve()?
+        );
+
+        let machine = ClawMachine::from_str(
+            "Button A: X+3, Y+1, Z+2 (cost 4)
+Button B: X+1, Y+2, Z+1 (cost 2)
+Button C: X+2, Z+3 (cost 3)
+Button D: X+1, Y+1, Z+1 (cost 3)
+Prize: X=20, Y=20, Z=20",
+        )?;
+        assert_eq!(
+            Some(Plan {
+                presses: vec![2, 8, 2, 2],
+                tokens: 36
+            }),
+            machine.solve()?
+        );
+
+        let machine = ClawMachine::from_str("Button A: X+2\nButton B: X+4\nPrize: X=7")?;
+        assert_eq!(None, machine.solve()?);
+        let machine = ClawMachine::from_str("Button A: X+2\nButton B: X-3\nPrize: X=1")?;
+        Ok(assert_eq!(
+            vec![2, 1],
+            machine.solve()?.ok_or(GenericError)?.presses
+        ))
+    }
 }
@@ -1,5 +1,12 @@
-use anyhow::Result;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    ops::Range,
+};
+
+use anyhow::{Context, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
 
 use crate::utils::AocError::*;
 
@@ -31,68 +38,293 @@ pub fn input_generator(input: &str) -> Result<Vec<Robot>> {
     Ok(matches)
 }
 
-fn simulate(grid: Coords, robot: &Robot, times: Base) -> Coords {
+// Stepped in i64 so that large velocities and times cannot overflow before wrapping.
+fn simulate(grid: Coords, robot: &Robot, times: i64) -> Coords {
+    let axis =
+        |p: Base, v: Base, n: Base| (p as i64 + v as i64 * times).rem_euclid(n as i64) as Base;
     (
-        (robot.0 .0 + robot.1 .0 * times).rem_euclid(grid.0),
-        (robot.0 .1 + robot.1 .1 * times).rem_euclid(grid.1),
+        axis(robot.0 .0, robot.1 .0, grid.0),
+        axis(robot.0 .1, robot.1 .1, grid.1),
     )
 }
 
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    a / gcd(a, b) * b
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuadrantStats {
+    pub time: Base,
+    pub counts: [usize; 4],
+    pub safety_factor: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    pub tiles: Vec<Coords>,
+    pub robots: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Swarm {
+    size: Coords,
+    time: Base,
+    robots: Vec<Robot>,
+}
+
+impl Swarm {
+    pub fn new(size: Coords, robots: &[Robot]) -> Self {
+        let robots = robots
+            .iter()
+            .map(|r| ((r.0 .0.rem_euclid(size.0), r.0 .1.rem_euclid(size.1)), r.1))
+            .collect();
+        Self {
+            size,
+            time: 0,
+            robots,
+        }
+    }
+
+    pub fn time(&self) -> Base {
+        self.time
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = Coords> + '_ {
+        self.robots.iter().map(|r| r.0)
+    }
+
+    pub fn step(&mut self) {
+        self.advance(1);
+    }
+
+    pub fn advance(&mut self, seconds: Base) {
+        let size = self.size;
+        let period = self.period();
+        for robot in self.robots.iter_mut() {
+            robot.0 = simulate(size, robot, (seconds as i64).rem_euclid(period));
+        }
+        self.time += seconds;
+    }
+
+    pub fn seek(&mut self, time: Base) {
+        self.advance(time - self.time);
+    }
+
+    // Each robot returns to its start once both coordinates have, which for an axis
+    // of length n and velocity v takes n / gcd(n, v) seconds.
+    pub fn period(&self) -> i64 {
+        let (w, h) = (self.size.0 as i64, self.size.1 as i64);
+        self.robots.iter().fold(1, |period, r| {
+            let x = w / gcd(w, r.1 .0 as i64);
+            let y = h / gcd(h, r.1 .1 as i64);
+            lcm(period, lcm(x, y))
+        })
+    }
+
+    pub fn quadrants(&self) -> QuadrantStats {
+        let (mx, my) = (self.size.0 / 2, self.size.1 / 2);
+        let mut counts = [0; 4];
+        for (x, y) in self.positions() {
+            if x == mx || y == my {
+                continue;
+            }
+            counts[(x > mx) as usize + 2 * (y > my) as usize] += 1;
+        }
+
+        QuadrantStats {
+            time: self.time,
+            counts,
+            safety_factor: counts.iter().product(),
+        }
+    }
+
+    pub fn quadrant_history(&self, times: Range<Base>) -> Vec<QuadrantStats> {
+        let mut swarm = self.clone();
+        times
+            .map(|t| {
+                swarm.seek(t);
+                swarm.quadrants()
+            })
+            .collect()
+    }
+
+    fn occupancy(&self) -> HashMap<Coords, usize> {
+        let mut tiles = HashMap::new();
+        for p in self.positions() {
+            *tiles.entry(p).or_default() += 1;
+        }
+        tiles
+    }
+
+    // Groups of occupied tiles touching each other, diagonals included, largest first.
+    pub fn clusters(&self) -> Vec<Cluster> {
+        let tiles = self.occupancy();
+        let mut seen = HashSet::new();
+        let mut clusters = vec![];
+
+        for start in tiles.keys().copied().sorted() {
+            if !seen.insert(start) {
+                continue;
+            }
+
+            let mut cluster = vec![];
+            let mut queue = vec![start];
+            while let Some(c) = queue.pop() {
+                cluster.push(c);
+                for n in (-1..=1)
+                    .cartesian_product(-1..=1)
+                    .map(|d| (c.0 + d.0, c.1 + d.1))
+                {
+                    if tiles.contains_key(&n) && seen.insert(n) {
+                        queue.push(n);
+                    }
+                }
+            }
+
+            cluster.sort();
+            clusters.push(Cluster {
+                robots: cluster.iter().map(|c| tiles[c]).sum(),
+                tiles: cluster,
+            });
+        }
+
+        clusters.sort_by(|a, b| b.robots.cmp(&a.robots).then(a.tiles.cmp(&b.tiles)));
+        clusters
+    }
+
+    pub fn render(&self) -> String {
+        let tiles = self.occupancy();
+        (0..self.size.1)
+            .map(|y| {
+                (0..self.size.0)
+                    .map(|x| match tiles.get(&(x, y)) {
+                        Some(n) => char::from_digit(*n.min(&9) as u32, 10).unwrap_or('#'),
+                        None => '.',
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
+    pub fn write_asciicast<W: Write>(
+        &self,
+        times: Range<Base>,
+        frame_delay: f64,
+        out: &mut W,
+    ) -> Result<()> {
+        let header = serde_json::json!({
+            "version": 2,
+            "width": self.size.0,
+            "height": self.size.1 + 1,
+        });
+        writeln!(out, "{header}")?;
+
+        let mut swarm = self.clone();
+        for (frame, t) in times.enumerate() {
+            swarm.seek(t);
+            let screen = format!(
+                "\u{1b}[H\u{1b}[2Jt={t}\r\n{}",
+                swarm.render().replace('\n', "\r\n")
+            );
+            let event = serde_json::json!([frame as f64 * frame_delay, "o", screen]);
+            writeln!(out, "{event}")?;
+        }
+        Ok(())
+    }
+}
+
 #[aoc(day14, part1)]
-pub fn solve_part1(input: &[Robot]) -> Result<Base> {
+pub fn solve_part1(input: &[Robot]) -> Result<usize> {
     let size = if input.len() == 12 {
         (11, 7)
     } else {
         (101, 103)
     };
-    let hash = input
+    let mut swarm = Swarm::new(size, input);
+    swarm.advance(100);
+    Ok(swarm.quadrants().safety_factor)
+}
+
+// How many standard deviations an axis spread has to drop below the spread of all
+// other frames before we trust it to be the picture rather than noise.
+const SIGNIFICANCE: f64 = 4.0;
+
+fn variance(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n
+}
+
+fn spread(robots: &[Robot], len: Base, axis: fn(&Coords) -> Base) -> Vec<f64> {
+    (0..len)
+        .map(|t| {
+            let values = robots
+                .iter()
+                .map(|r| (axis(&r.0) + axis(&r.1) * t).rem_euclid(len) as f64)
+                .collect::<Vec<_>>();
+            variance(&values)
+        })
+        .collect()
+}
+
+fn significant_minimum(values: &[f64]) -> Option<i64> {
+    let (t, min) = values
         .iter()
-        .map(|m| simulate(size, m, 100))
-        .fold((0, 0, 0, 0), |acc, m| match m {
-            (x, y) if x < size.0 / 2 && y < size.1 / 2 => (acc.0 + 1, acc.1, acc.2, acc.3),
-            (x, y) if x > size.0 / 2 && y < size.1 / 2 => (acc.0, acc.1 + 1, acc.2, acc.3),
-            (x, y) if x < size.0 / 2 && y > size.1 / 2 => (acc.0, acc.1, acc.2 + 1, acc.3),
-            (x, y) if x > size.0 / 2 && y > size.1 / 2 => (acc.0, acc.1, acc.2, acc.3 + 1),
-            _ => acc,
-        });
-    Ok(hash.0 * hash.1 * hash.2 * hash.3)
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))?;
+    let others = values
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != t)
+        .map(|(_, v)| *v)
+        .collect::<Vec<_>>();
+    let mean = others.iter().sum::<f64>() / others.len() as f64;
+    (*min < mean - SIGNIFICANCE * variance(&others).sqrt()).then_some(t as i64)
 }
 
-fn score(size: Coords, m: &[Robot]) -> i32 {
-    let mut score = 0;
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - a / b * y)
+    }
+}
 
-    for x in 0..=size.0 {
-        for y in 0..=size.1 {
-            let on_this_tile = m.iter().filter(|m| m.0 .0 == x && m.0 .1 == y).count();
-            score += if on_this_tile > 1 { 1 } else { 0 };
-        }
+// Smallest t >= 0 with t = a (mod m) and t = b (mod n), if the two agree.
+fn crt(a: i64, m: i64, b: i64, n: i64) -> Option<i64> {
+    let (g, p, _) = extended_gcd(m, n);
+    if (b - a) % g != 0 {
+        return None;
     }
 
-    score
+    let lcm = m / g * n;
+    let k = ((b - a) / g * p).rem_euclid(n / g);
+    Some((a + m * k).rem_euclid(lcm))
 }
 
-fn print_robots(size: Coords, m: &[Robot]) {
-    for x in 0..=size.0 {
-        for y in 0..=size.1 {
-            let on_this_tile = m.iter().filter(|m| m.0 .0 == x && m.0 .1 == y).count();
-            let c = if on_this_tile > 0 { 'x' } else { ' ' };
-            print!("{}", c);
-        }
-        println!();
-    }
+// Every robot's x repeats with the grid width and its y with the grid height, so the
+// picture frame minimises the spread along each axis independently. The two minima
+// pin down the frame modulo the lcm of the grid dimensions.
+pub fn find_easter_egg(size: Coords, robots: &[Robot]) -> Option<Base> {
+    let tx = significant_minimum(&spread(robots, size.0, |c| c.0))?;
+    let ty = significant_minimum(&spread(robots, size.1, |c| c.1))?;
+    crt(tx, size.0 as i64, ty, size.1 as i64).map(|t| t as Base)
 }
 
 #[aoc(day14, part2)]
 pub fn solve_part2(input: &[Robot]) -> Result<Base> {
-    let size = (101, 103);
-    let machines = input
-        .iter()
-        .map(|m| (simulate(size, m, 8053), m.1))
-        .collect::<Vec<_>>();
-    println!("Score: {}", score(size, &machines));
-    print_robots(size, &machines);
-
-    Ok(8053)
+    find_easter_egg((101, 103), input)
+        .ok_or(GenericError)
+        .context("Could not find the easter egg")
 }
 
 #[cfg(test)]
@@ -124,4 +356,128 @@ p=9,5 v=-3,-3"
         let data = input_generator(input())?;
         Ok(assert_eq!(12, solve_part1(&data)?))
     }
+
+    fn synthetic(size: Coords, time: Base, pattern: &[Coords], noise: usize) -> Vec<Robot> {
+        let mut state = 0x2024_u64;
+        let mut random = |n: Base| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) % n as u64) as Base
+        };
+
+        let placed = pattern
+            .iter()
+            .copied()
+            .chain((0..noise).map(|_| (random(size.0), random(size.1))))
+            .collect::<Vec<_>>();
+        placed
+            .into_iter()
+            .map(|p| {
+                let v = (random(2 * size.0) - size.0, random(2 * size.1) - size.1);
+                let start = simulate(size, &(p, (-v.0, -v.1)), time as i64);
+                (start, v)
+            })
+            .collect()
+    }
+
+    fn tree(top: Coords, height: Base) -> Vec<Coords> {
+        (0..height)
+            .flat_map(|r| (-r..=r).map(move |c| (top.0 + c, top.1 + r)))
+            .collect()
+    }
+
+    #[test]
+    fn easter_egg() {
+        let robots = synthetic((101, 103), 4321, &tree((50, 30), 12), 350);
+        assert_eq!(Some(4321), find_easter_egg((101, 103), &robots));
+
+        let robots = synthetic((45, 32), 1000, &tree((22, 10), 9), 30);
+        assert_eq!(Some(1000), find_easter_egg((45, 32), &robots));
+
+        let robots = synthetic((40, 30), 77, &tree((20, 10), 9), 30);
+        assert_eq!(Some(77), find_easter_egg((40, 30), &robots));
+
+        let robots = synthetic((101, 103), 0, &[], 500);
+        assert_eq!(None, find_easter_egg((101, 103), &robots));
+    }
+
+    #[test]
+    fn swarm() -> Result<()> {
+        let data = input_generator(input())?;
+        let mut swarm = Swarm::new((11, 7), &data);
+        assert_eq!(
+            "1.12.......
+...........
+...........
+......11.11
+1.1........
+.........1.
+.......1...",
+            swarm.render()
+        );
+
+        let history = swarm.quadrant_history(0..101);
+        assert_eq!(101, history.len());
+        assert_eq!([1, 3, 4, 1], history[100].counts);
+        assert_eq!(12, history[100].safety_factor);
+
+        assert_eq!(77, swarm.period());
+        let start = swarm.positions().collect::<Vec<_>>();
+        (0..77).for_each(|_| swarm.step());
+        assert_eq!(77, swarm.time());
+        Ok(assert_eq!(start, swarm.positions().collect::<Vec<_>>()))
+    }
+
+    #[test]
+    fn large_coprime_grid() {
+        let mut swarm = Swarm::new((2000, 2001), &[((0, 0), (1999, 1999))]);
+        assert_eq!(4_002_000, swarm.period());
+        swarm.advance(4_000_000);
+        assert_eq!(vec![(0, 1999)], swarm.positions().collect::<Vec<_>>());
+        swarm.seek(4_002_000);
+        assert_eq!(vec![(0, 0)], swarm.positions().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn clusters() {
+        let robots = [(0, 0), (1, 1), (5, 5), (5, 5), (5, 5), (9, 0)].map(|p| (p, (0, 2)));
+        let swarm = Swarm::new((10, 6), &robots);
+        assert_eq!(3, swarm.period());
+        assert_eq!(
+            vec![
+                Cluster {
+                    tiles: vec![(5, 5)],
+                    robots: 3
+                },
+                Cluster {
+                    tiles: vec![(0, 0), (1, 1)],
+                    robots: 2
+                },
+                Cluster {
+                    tiles: vec![(9, 0)],
+                    robots: 1
+                }
+            ],
+            swarm.clusters()
+        );
+    }
+
+    #[test]
+    fn asciicast() -> Result<()> {
+        let data = input_generator(input())?;
+        let mut out = vec![];
+        Swarm::new((11, 7), &data).write_asciicast(0..3, 0.5, &mut out)?;
+
+        let lines = String::from_utf8(out)?
+            .lines()
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(4, lines.len());
+        assert_eq!(8, lines[0]["height"]);
+        assert_eq!(1.0, lines[3][0]);
+        Ok(assert!(lines[3][2]
+            .as_str()
+            .is_some_and(|s| s.contains("t=2\r\n"))))
+    }
 }
@@ -4,5 +4,7 @@ use thiserror::Error;
 pub enum AocError {
     #[error("Generic error")]
     GenericError,
+    #[error("Cyclic rules: {0:?}")]
+    CyclicRules(Vec<u32>),
 }
 